@@ -0,0 +1,117 @@
+//! Compares the unboxed `CorsBody` response with boxing every response
+//! body into `axum::body::Body`, which is what `CorsService` used to do.
+
+use axum::body::Body;
+use axum_cors::*;
+use criterion::{criterion_group, criterion_main, Criterion};
+use futures_util::FutureExt;
+use http::{header, Method, Request, Response};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    convert::Infallible,
+    future::{ready, Ready},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use tower::{service_fn, Layer, Service, ServiceExt};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn inner(_: Request<()>) -> Ready<Result<Response<String>, Infallible>> {
+    ready(Ok(Response::new(String::from("ok"))))
+}
+
+fn request() -> Request<()> {
+    Request::builder()
+        .method(Method::POST)
+        .header(header::ORIGIN, "http://test.example")
+        .body(())
+        .unwrap()
+}
+
+fn call<S>(service: &mut S) -> S::Response
+where
+    S: Service<Request<()>>,
+    S::Error: std::fmt::Debug,
+{
+    ready_now(service)
+        .call(request())
+        .now_or_never()
+        .expect("service is ready")
+        .unwrap()
+}
+
+fn ready_now<S>(service: &mut S) -> &mut S
+where
+    S: Service<Request<()>>,
+    S::Error: std::fmt::Debug,
+{
+    service
+        .ready()
+        .now_or_never()
+        .expect("service is ready")
+        .unwrap()
+}
+
+fn allocations_per_call<S>(service: &mut S) -> usize
+where
+    S: Service<Request<()>>,
+    S::Error: std::fmt::Debug,
+{
+    // The first request initializes lazily allocated state
+    drop(call(service));
+
+    let request = request();
+    ready_now(service);
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let response = service.call(request).now_or_never();
+    let after = ALLOCATIONS.load(Ordering::Relaxed);
+    drop(response);
+    after - before
+}
+
+fn response_body(c: &mut Criterion) {
+    let layer = CorsBuilder::new()
+        .allow_origins(AllowedOrigins::Any { allow_null: false })
+        .allow_methods(vec![Method::POST])
+        .into_layer();
+
+    let mut passthrough = layer.layer(service_fn(inner));
+    let mut boxed = layer
+        .layer(service_fn(inner))
+        .map_response(|response| response.map(Body::new));
+
+    let (passthrough_allocations, boxed_allocations) = (
+        allocations_per_call(&mut passthrough),
+        allocations_per_call(&mut boxed),
+    );
+    assert!(
+        passthrough_allocations < boxed_allocations,
+        "the passthrough body allocates {} times per request, boxing {} times",
+        passthrough_allocations,
+        boxed_allocations
+    );
+
+    let mut group = c.benchmark_group("response_body");
+    group.bench_function("passthrough", |b| b.iter(|| call(&mut passthrough)));
+    group.bench_function("boxed", |b| b.iter(|| call(&mut boxed)));
+    group.finish();
+}
+
+criterion_group!(benches, response_body);
+criterion_main!(benches);
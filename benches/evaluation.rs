@@ -0,0 +1,134 @@
+//! `Config::process_request` across the size and kind of the origin
+//! allowlist, and the `CorsService` round trip around a trivial service.
+
+use axum_cors::*;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use http::{header, HeaderValue, Method, Request};
+
+/// The allowed origin the requests are sent from, the last of the allowlist
+fn origin(allowlist: usize) -> String {
+    format!("https://app{}.example", allowlist - 1)
+}
+
+fn allowlist(size: usize) -> AllowedOrigins {
+    (0..size)
+        .map(|i| HeaderValue::from_str(&format!("https://app{}.example", i)).unwrap())
+        .collect()
+}
+
+fn config(allowed_origins: AllowedOrigins) -> Config {
+    CorsBuilder::new()
+        .allow_origins(allowed_origins)
+        .allow_methods(vec![Method::GET, Method::POST, Method::PUT])
+        .allow_headers(vec![header::CONTENT_TYPE])
+        .into_config()
+}
+
+fn preflight(origin: &str) -> Request<()> {
+    Request::builder()
+        .method(Method::OPTIONS)
+        .header(header::ORIGIN, origin)
+        .header(header::ACCESS_CONTROL_REQUEST_METHOD, "PUT")
+        .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type")
+        .body(())
+        .unwrap()
+}
+
+fn simple(origin: &str) -> Request<()> {
+    Request::builder()
+        .method(Method::POST)
+        .header(header::ORIGIN, origin)
+        .body(())
+        .unwrap()
+}
+
+fn no_origin() -> Request<()> {
+    Request::builder().method(Method::GET).body(()).unwrap()
+}
+
+fn allowlist_size(c: &mut Criterion) {
+    let mut group = c.benchmark_group("allowlist");
+    for size in [1, 100, 10_000] {
+        let config = config(allowlist(size));
+        let origin = origin(size);
+        let requests = [
+            ("preflight", preflight(&origin)),
+            ("simple", simple(&origin)),
+            ("no_origin", no_origin()),
+        ];
+        for (kind, request) in requests.iter() {
+            group.bench_with_input(BenchmarkId::new(*kind, size), request, |b, request| {
+                b.iter(|| config.process_request(black_box(request)))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn wildcard_patterns(c: &mut Criterion) {
+    let config = config(
+        AllowedOrigins::try_from_patterns(vec![
+            "https://*.corp.example",
+            "https://*.staging.example:8000-8999",
+            "http://localhost:*",
+        ])
+        .unwrap(),
+    );
+    let matching = simple("https://app.staging.example:8443");
+    let unmatched = simple("https://app.elsewhere.example");
+
+    let mut group = c.benchmark_group("patterns");
+    group.bench_function("matching", |b| {
+        b.iter(|| config.process_request(black_box(&matching)))
+    });
+    group.bench_function("unmatched", |b| {
+        b.iter(|| config.process_request(black_box(&unmatched)))
+    });
+    group.finish();
+}
+
+#[cfg(feature = "tower")]
+fn service_round_trip(c: &mut Criterion) {
+    use futures_util::FutureExt;
+    use http::Response;
+    use std::{convert::Infallible, future::ready, sync::Arc};
+    use tower::{service_fn, Layer, Service, ServiceExt};
+
+    let layer = CorsLayer::new(Arc::new(config(allowlist(100))));
+    let mut service = layer.layer(service_fn(|_: Request<()>| {
+        ready(Ok::<_, Infallible>(Response::new(())))
+    }));
+    let origin = origin(100);
+
+    let mut group = c.benchmark_group("service");
+    for (kind, request) in [
+        ("preflight", preflight as fn(&str) -> _),
+        ("simple", simple),
+    ] {
+        group.bench_function(kind, |b| {
+            b.iter(|| {
+                service
+                    .ready()
+                    .now_or_never()
+                    .expect("service is ready")
+                    .unwrap()
+                    .call(request(&origin))
+                    .now_or_never()
+                    .expect("service is ready")
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+#[cfg(not(feature = "tower"))]
+fn service_round_trip(_: &mut Criterion) {}
+
+criterion_group!(
+    benches,
+    allowlist_size,
+    wildcard_patterns,
+    service_round_trip
+);
+criterion_main!(benches);
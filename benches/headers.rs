@@ -0,0 +1,65 @@
+//! Building the response headers of each response shape from the templates
+//! precomputed by `CorsBuilder::into_config`.
+
+use axum_cors::*;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use http::{header, HeaderName, Method, Request};
+use std::time::Duration;
+
+const ORIGIN: &str = "https://app.example";
+
+fn builder() -> CorsBuilder {
+    CorsBuilder::new()
+        .allow_origins(AllowedOrigins::Any { allow_null: false })
+        .allow_methods(vec![Method::GET, Method::POST, Method::PUT])
+        .allow_headers(vec![header::CONTENT_TYPE, header::AUTHORIZATION])
+        .expose_headers(vec![HeaderName::from_static("x-request-id")])
+        .max_age(Duration::from_secs(600))
+}
+
+fn simple() -> Request<()> {
+    Request::builder()
+        .method(Method::POST)
+        .header(header::ORIGIN, ORIGIN)
+        .body(())
+        .unwrap()
+}
+
+fn preflight() -> Request<()> {
+    Request::builder()
+        .method(Method::OPTIONS)
+        .header(header::ORIGIN, ORIGIN)
+        .header(header::ACCESS_CONTROL_REQUEST_METHOD, "PUT")
+        .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type")
+        .body(())
+        .unwrap()
+}
+
+fn no_origin() -> Request<()> {
+    Request::builder().method(Method::GET).body(()).unwrap()
+}
+
+fn response_headers(c: &mut Criterion) {
+    let wildcard = builder().prefer_wildcard(true).into_config();
+    let reflected = builder().into_config();
+
+    let (simple, preflight, no_origin) = (simple(), preflight(), no_origin());
+
+    let mut group = c.benchmark_group("response_headers");
+    group.bench_function("wildcard_simple", |b| {
+        b.iter(|| wildcard.process_request(black_box(&simple)))
+    });
+    group.bench_function("reflected_simple", |b| {
+        b.iter(|| reflected.process_request(black_box(&simple)))
+    });
+    group.bench_function("preflight", |b| {
+        b.iter(|| reflected.process_request(black_box(&preflight)))
+    });
+    group.bench_function("no_origin", |b| {
+        b.iter(|| reflected.process_request(black_box(&no_origin)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, response_headers);
+criterion_main!(benches);
@@ -0,0 +1,49 @@
+//! Lookups in an `OriginIndex` of 100,000 entries: 50,000 exact origins and
+//! 50,000 subdomain patterns.
+
+use axum_cors::*;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use http::HeaderValue;
+
+const ENTRIES: usize = 100_000;
+
+fn index() -> OriginIndex {
+    let mut index = OriginIndex::new();
+    for i in 0..ENTRIES / 2 {
+        index
+            .insert_origin(&format!("https://app.tenant{}.example", i))
+            .unwrap();
+        index
+            .insert_pattern(&format!("https://*.tenant{}.example.net", i))
+            .unwrap();
+    }
+    index
+}
+
+fn lookup(c: &mut Criterion) {
+    let index = index();
+    assert_eq!(index.len(), ENTRIES);
+
+    let cases = [
+        ("exact", "https://app.tenant31337.example", true),
+        ("subdomain", "https://a.b.tenant31337.example.net", true),
+        ("miss", "https://app.tenant31337.example.org", false),
+    ];
+
+    let mut group = c.benchmark_group("index");
+    for (name, origin, allowed) in cases.iter() {
+        let parsed = Origin::parse(origin).unwrap();
+        assert_eq!(index.matches(&parsed), *allowed);
+        group.bench_function(*name, |b| b.iter(|| index.matches(black_box(&parsed))));
+
+        // Including the parsing and normalization of the `Origin` header
+        let header = HeaderValue::from_static(origin);
+        group.bench_function(format!("{}_from_header", name), |b| {
+            b.iter(|| index.matches(&Origin::from_header(black_box(&header)).unwrap()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, lookup);
+criterion_main!(benches);
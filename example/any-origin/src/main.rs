@@ -1,4 +1,4 @@
-use axum::{handler::get, Router};
+use axum::{routing::get, Router};
 use axum_cors::*;
 use http::{header, Method};
 use std::net::SocketAddr;
@@ -22,10 +22,8 @@ async fn main() {
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
     println!("listening on {}", addr);
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    axum::serve(listener, app).await.unwrap();
 }
 
 async fn handler() -> &'static str {
@@ -0,0 +1,63 @@
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// The response body of `CorsService`: either the body of the inner
+    /// service, passed through without boxing, or an empty body for
+    /// requests answered by the CORS layer itself.
+    #[project = CorsBodyProj]
+    #[derive(Debug)]
+    pub enum CorsBody<B> {
+        Inner { #[pin] body: B },
+        Empty,
+    }
+}
+
+impl<B> CorsBody<B> {
+    pub fn inner(body: B) -> Self {
+        CorsBody::Inner { body }
+    }
+
+    pub fn empty() -> Self {
+        CorsBody::Empty
+    }
+}
+
+impl<B> Body for CorsBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    #[inline]
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match self.project() {
+            CorsBodyProj::Inner { body } => body.poll_frame(cx),
+            CorsBodyProj::Empty => Poll::Ready(None),
+        }
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        match self {
+            CorsBody::Inner { body } => body.is_end_stream(),
+            CorsBody::Empty => true,
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> SizeHint {
+        match self {
+            CorsBody::Inner { body } => body.size_hint(),
+            CorsBody::Empty => SizeHint::with_exact(0),
+        }
+    }
+}
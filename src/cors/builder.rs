@@ -1,23 +1,46 @@
-use super::config::{AllowedOrigins, Config};
+use super::config::{AllowedOrigins, Config, HeaderTemplates, TimingAllowOrigin};
+use super::csrf::CsrfProtection;
+use super::fetch_metadata::ResourceIsolation;
+#[cfg(feature = "tower")]
 use super::layer::CorsLayer;
+use super::methods::MethodSet;
+#[cfg(feature = "metrics")]
+use super::metrics::CorsMetrics;
+use super::origin::InvalidOrigin;
+use super::report::{Divergence, ReportOnly};
+use super::routes::RouteMethods;
+use super::same_origin::SameOrigin;
+use super::websocket::WebSocketProtection;
 
 use http::{
     header::{self, HeaderName, HeaderValue},
     Method,
 };
+#[cfg(feature = "tower")]
 use std::sync::Arc;
 use std::{collections::HashSet, time::Duration};
 
 /// Build a configured CORS middleware instance.
 #[derive(Debug, Default, Clone)]
 pub struct CorsBuilder {
-    allowed_methods: HashSet<Method>,
+    allowed_methods: MethodSet,
     allowed_origins: AllowedOrigins,
     allowed_headers: HashSet<HeaderName>,
     allow_credentials: bool,
+    csrf_protection: Option<CsrfProtection>,
     exposed_headers: HashSet<HeaderName>,
     max_age: Option<Duration>,
     prefer_wildcard: bool,
+    denial_level: Option<log::Level>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<CorsMetrics>,
+    report_only: Option<ReportOnly>,
+    route_methods: Option<RouteMethods>,
+    resource_isolation: Option<ResourceIsolation>,
+    same_origin: Option<SameOrigin>,
+    timing_allow_origin: Option<TimingAllowOrigin>,
+    websocket: Option<WebSocketProtection>,
+    development: Option<bool>,
 }
 
 impl CorsBuilder {
@@ -28,6 +51,65 @@ impl CorsBuilder {
         Default::default()
     }
 
+    /// A permissive preset for local development, allowing frontends served
+    /// from any loopback origin (`http://localhost:<port>`,
+    /// `http://127.0.0.1:<port>`, `http://[::1]:<port>`) to use the common
+    /// methods and headers.
+    ///
+    /// The loopback origins are only allowed once `enable_development(true)`
+    /// is called, e.g. with `cfg!(debug_assertions)`. Otherwise every
+    /// cross-origin request is denied.
+    pub fn development() -> CorsBuilder {
+        let mut builder = CorsBuilder::new()
+            .allow_methods(vec![
+                Method::GET,
+                Method::HEAD,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::DELETE,
+            ])
+            .allow_headers(vec![
+                header::ACCEPT,
+                header::ACCEPT_LANGUAGE,
+                header::AUTHORIZATION,
+                header::CONTENT_LANGUAGE,
+                header::CONTENT_TYPE,
+            ]);
+        builder.development = Some(false);
+        builder
+    }
+
+    /// A preset for gRPC-web clients, allowing `POST` with the headers
+    /// gRPC-web sends and exposing the status headers of a response, so
+    /// that trailers-only responses can be read.
+    ///
+    /// No origin is allowed, set them with `allow_origins`.
+    pub fn grpc_web() -> CorsBuilder {
+        CorsBuilder::new()
+            .allow_methods(vec![Method::POST])
+            .allow_headers(vec![
+                header::CONTENT_TYPE,
+                HeaderName::from_static("x-grpc-web"),
+                HeaderName::from_static("x-user-agent"),
+                HeaderName::from_static("grpc-timeout"),
+            ])
+            .expose_headers(vec![
+                HeaderName::from_static("grpc-status"),
+                HeaderName::from_static("grpc-message"),
+                HeaderName::from_static("grpc-status-details-bin"),
+            ])
+    }
+
+    /// Additionally allow the `http` and `https` origins on loopback hosts,
+    /// see `development`. This works on any builder, e.g.
+    /// `enable_development(cfg!(debug_assertions))` on the production
+    /// configuration.
+    pub fn enable_development(mut self, enabled: bool) -> Self {
+        self.development = Some(enabled);
+        self
+    }
+
     /// Add origins which are allowed to access this resource
     pub fn allow_origins(mut self, origins: AllowedOrigins) -> Self {
         self.allowed_origins = origins;
@@ -45,6 +127,17 @@ impl CorsBuilder {
         self
     }
 
+    /// Allow the methods each route supports instead of `allow_methods`,
+    /// so that preflights list exactly the methods of the target path.
+    /// Paths without a route fall back to `allow_methods`.
+    ///
+    /// Plain `OPTIONS` requests to a route which does not handle `OPTIONS`
+    /// itself are answered with its methods in `Allow`.
+    pub fn route_methods(mut self, routes: RouteMethods) -> Self {
+        self.route_methods = Some(routes);
+        self
+    }
+
     /// Add headers which are allowed to be sent to this resource
     pub fn allow_headers<I>(mut self, headers: I) -> Self
     where
@@ -62,6 +155,15 @@ impl CorsBuilder {
         self
     }
 
+    /// Require unsafe requests (e.g. `POST`) to come from the same origin or
+    /// an allowed origin, as given by `Origin` or else `Referer`. This
+    /// protects cookie-authenticated endpoints from cross-site form posts,
+    /// which are sent without a preflight. See `CsrfProtection`.
+    pub fn csrf_protection(mut self, csrf_protection: CsrfProtection) -> Self {
+        self.csrf_protection = Some(csrf_protection);
+        self
+    }
+
     /// Add headers which are allowed to be read from the response from this resource
     pub fn expose_headers<I>(mut self, headers: I) -> Self
     where
@@ -91,21 +193,145 @@ impl CorsBuilder {
         self
     }
 
+    /// Reject cross-site requests based on their Fetch Metadata, unless
+    /// their origin is allowed, including requests CORS never sees such as
+    /// cross-site form posts and image loads. See `ResourceIsolation`.
+    pub fn resource_isolation(mut self, policy: ResourceIsolation) -> Self {
+        self.resource_isolation = Some(policy);
+        self
+    }
+
+    /// Detect requests from the origin of the server itself, which bypass
+    /// the CORS checks and receive no CORS headers. Behind a reverse proxy,
+    /// trust the headers it forwards with `SameOrigin::trust`.
+    ///
+    /// The same detection is used by `csrf_protection`, which otherwise
+    /// only relies on the `Host` header and the URI of the request.
+    pub fn same_origin(mut self, same_origin: SameOrigin) -> Self {
+        self.same_origin = Some(same_origin);
+        self
+    }
+
+    /// Send `Timing-Allow-Origin` on actual responses to allowed origins,
+    /// so that they can read the detailed Resource Timing of the response.
+    ///
+    /// This is independent of `prefer_wildcard`: the origin may be
+    /// reflected in one header and wildcarded in the other.
+    pub fn timing_allow_origin(mut self, timing_allow_origin: TimingAllowOrigin) -> Self {
+        self.timing_allow_origin = Some(timing_allow_origin);
+        self
+    }
+
+    /// Check the origin of WebSocket handshakes, which browsers do not
+    /// subject to CORS. Handshakes from other origins are rejected with
+    /// `403 Forbidden` before they reach the handler. See
+    /// `WebSocketProtection`.
+    pub fn websocket(mut self, websocket: WebSocketProtection) -> Self {
+        self.websocket = Some(websocket);
+        self
+    }
+
+    /// The level at which denied CORS requests are logged. Defaults to
+    /// `Debug`.
+    ///
+    /// With the `tracing` feature enabled the denial is emitted as a
+    /// `tracing` event at the corresponding level as well as a `log`
+    /// record.
+    pub fn denial_level(mut self, level: log::Level) -> Self {
+        self.denial_level = Some(level);
+        self
+    }
+
+    /// Record counters for every request evaluated by `Config::process_request`,
+    /// which includes every request handled by the `CorsService`
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: CorsMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Evaluate `candidate` alongside this configuration without enforcing
+    /// it, and pass every request on which both disagree to `reporter`.
+    ///
+    /// This allows to roll out a stricter policy by first observing which
+    /// clients it would break.
+    pub fn report_only<F>(mut self, candidate: Config, reporter: F) -> Self
+    where
+        F: Fn(&Divergence<'_>) + Send + Sync + 'static,
+    {
+        self.report_only = Some(ReportOnly::new(candidate, reporter));
+        self
+    }
+
+    /// The allowed origins, including the loopback origins while
+    /// development is enabled
+    pub(crate) fn effective_allowed_origins(&self) -> AllowedOrigins {
+        match self.development {
+            Some(true) => self.allowed_origins.clone().with_loopback(),
+            _ => self.allowed_origins.clone(),
+        }
+    }
+
+    /// Build the configuration like `into_config`, but fail on an invalid
+    /// allowed origin instead of skipping it
+    pub fn try_into_config(self) -> Result<Config, InvalidOrigin> {
+        self.allowed_origins.clone().normalized()?;
+        if let Some(origins) = self.websocket.as_ref().and_then(|w| w.allowed_origins()) {
+            origins.clone().normalized()?;
+        }
+        Ok(self.into_config())
+    }
+
+    /// Build the configuration. Invalid allowed origins are logged and
+    /// skipped, see `try_into_config`.
     pub fn into_config(self) -> Config {
         let Self {
             allow_credentials,
             allowed_headers,
             allowed_methods,
             allowed_origins,
+            csrf_protection,
             exposed_headers,
             max_age,
             prefer_wildcard,
+            denial_level,
+            #[cfg(feature = "metrics")]
+            metrics,
+            report_only,
+            route_methods,
+            resource_isolation,
+            same_origin,
+            timing_allow_origin,
+            websocket,
+            development,
         } = self;
 
+        let allowed_origins = match development {
+            Some(false) => {
+                if matches!(allowed_origins, AllowedOrigins::Origins(ref origins) if origins.is_empty())
+                {
+                    log::warn!(
+                        "CORS development mode is not enabled and no origin is allowed, \
+                         all cross-origin requests are denied"
+                    );
+                }
+                allowed_origins
+            }
+            Some(true) => {
+                if allow_credentials {
+                    log::warn!(
+                        "CORS development mode is enabled with credentials, \
+                         any local page can make credentialed requests"
+                    );
+                }
+                allowed_origins.with_loopback()
+            }
+            None => allowed_origins,
+        };
+        let allowed_origins = allowed_origins.normalized_or_skip();
         let allowed_headers_header =
             join_header_value(&allowed_headers).expect("Invalid allowed headers");
-        let allowed_methods_header =
-            join_header_value(&allowed_methods).expect("Invalid allowed methods");
+        let allowed_methods_header = allowed_methods.to_header_value();
         let exposed_headers_header = if exposed_headers.is_empty() {
             None
         } else {
@@ -120,20 +346,34 @@ impl CorsBuilder {
         ])
         .expect("Invalid vary");
 
-        Config {
+        let mut config = Config {
             allow_credentials,
             allowed_headers,
             allowed_headers_header,
             allowed_methods,
             allowed_methods_header,
             allowed_origins,
+            csrf_protection,
+            denial_level: denial_level.unwrap_or(log::Level::Debug),
             exposed_headers_header,
             max_age,
+            #[cfg(feature = "metrics")]
+            metrics,
             prefer_wildcard,
+            report_only,
+            route_methods,
+            resource_isolation,
+            same_origin,
+            timing_allow_origin,
             vary_header,
-        }
+            websocket: websocket.map(WebSocketProtection::normalized),
+            templates: HeaderTemplates::default(),
+        };
+        config.templates = HeaderTemplates::new(&config);
+        config
     }
 
+    #[cfg(feature = "tower")]
     pub fn into_layer(self) -> CorsLayer {
         CorsLayer::new(Arc::new(self.into_config()))
     }
@@ -2,13 +2,27 @@ use http::{
     header::{self, HeaderMap, HeaderName, HeaderValue},
     method, Method, Request,
 };
-use log;
 use std::{
     collections::{BTreeSet, HashSet},
     error, fmt,
     iter::FromIterator,
 };
 
+use super::csrf::{CsrfProtection, RequestSource};
+use super::fetch_metadata::{Isolation, ResourceIsolation};
+use super::index::OriginIndex;
+use super::methods::MethodSet;
+#[cfg(feature = "metrics")]
+use super::metrics::CorsMetrics;
+use super::origin::{InvalidOrigin, Origin};
+use super::pattern::OriginPattern;
+use super::report::ReportOnly;
+use super::routes::{RouteMatch, RouteMethods};
+use super::same_origin::{SameOrigin, HOST_ONLY};
+#[cfg(feature = "tracing")]
+use super::trace;
+use super::websocket::{is_websocket_upgrade, WebSocketProtection};
+
 /// Specifies which origins are allowed to access this resource
 #[derive(Debug, Clone)]
 pub enum AllowedOrigins {
@@ -23,17 +37,186 @@ pub enum AllowedOrigins {
 
     /// Allow a specific set of origins
     Origins(BTreeSet<HeaderValue>),
+
+    /// Allow every origin matching one of the patterns, see `OriginPattern`
+    Patterns(Vec<OriginPattern>),
+
+    /// Allow the origins and patterns of an index, for allowlists too large
+    /// to be matched one by one, see `OriginIndex`
+    Indexed(OriginIndex),
 }
 
 impl AllowedOrigins {
-    fn origin_allowed(&self, origin: &HeaderValue) -> bool {
+    /// Allow a specific set of origins. Each origin is normalized, and
+    /// origins with a path, query or fragment are rejected.
+    pub fn try_from_origins<I>(origins: I) -> Result<Self, InvalidOrigin>
+    where
+        I: IntoIterator,
+        I::Item: Into<HeaderValue>,
+    {
+        #[allow(clippy::mutable_key_type)]
+        let origins = origins
+            .into_iter()
+            .map(|origin| normalize_origin(&origin.into()))
+            .collect::<Result<_, _>>()?;
+        Ok(AllowedOrigins::Origins(origins))
+    }
+
+    /// Allow `http` and `https` origins on a loopback host (`localhost`,
+    /// `127.0.0.0/8` or `[::1]`) on any port
+    pub fn loopback() -> Self {
+        AllowedOrigins::Patterns(loopback_patterns().collect())
+    }
+
+    /// Additionally allow the origins of `loopback`
+    pub(crate) fn with_loopback(self) -> Self {
+        match self {
+            AllowedOrigins::Any { allow_null } => AllowedOrigins::Any { allow_null },
+            AllowedOrigins::Origins(ref origins) if origins.is_empty() => {
+                AllowedOrigins::loopback()
+            }
+            AllowedOrigins::Origins(origins) => {
+                let mut index = OriginIndex::new();
+                for origin in &origins {
+                    let inserted = std::str::from_utf8(origin.as_bytes())
+                        .map_err(|_| InvalidOrigin::Encoding)
+                        .and_then(|origin| index.insert_origin(origin));
+                    if let Err(e) = inserted {
+                        log::warn!("ignoring invalid allowed origin {:?}: {}", origin, e);
+                    }
+                }
+                loopback_patterns().for_each(|pattern| index.add_pattern(pattern));
+                AllowedOrigins::Indexed(index)
+            }
+            AllowedOrigins::Patterns(mut patterns) => {
+                patterns.extend(loopback_patterns());
+                AllowedOrigins::Patterns(patterns)
+            }
+            AllowedOrigins::Indexed(mut index) => {
+                loopback_patterns().for_each(|pattern| index.add_pattern(pattern));
+                AllowedOrigins::Indexed(index)
+            }
+        }
+    }
+
+    /// Allow every origin matching one of the patterns, e.g.
+    /// `https://*.corp.example:8000-8999`
+    pub fn try_from_patterns<I>(patterns: I) -> Result<Self, InvalidOrigin>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let patterns = patterns
+            .into_iter()
+            .map(|pattern| OriginPattern::parse(pattern.as_ref()))
+            .collect::<Result<_, _>>()?;
+        Ok(AllowedOrigins::Patterns(patterns))
+    }
+
+    /// Allow a large set of origins and patterns, matched through an
+    /// `OriginIndex`
+    pub fn try_indexed<O, P>(origins: O, patterns: P) -> Result<Self, InvalidOrigin>
+    where
+        O: IntoIterator,
+        O::Item: AsRef<str>,
+        P: IntoIterator,
+        P::Item: AsRef<str>,
+    {
+        let mut index = OriginIndex::new();
+        for origin in origins {
+            index.insert_origin(origin.as_ref())?;
+        }
+        for pattern in patterns {
+            index.insert_pattern(pattern.as_ref())?;
+        }
+        Ok(AllowedOrigins::Indexed(index))
+    }
+
+    /// Normalize every configured origin, so that they can be compared
+    /// against normalized request origins
+    pub fn normalized(self) -> Result<Self, InvalidOrigin> {
+        match self {
+            AllowedOrigins::Origins(origins) => AllowedOrigins::try_from_origins(origins),
+            any => Ok(any),
+        }
+    }
+
+    /// Normalize every configured origin like `normalized`, but log and
+    /// skip the invalid ones, which no request origin could match
+    pub(crate) fn normalized_or_skip(self) -> Self {
         match self {
-            AllowedOrigins::Any { allow_null } => {
-                *allow_null || origin != HeaderValue::from_static("null")
+            AllowedOrigins::Origins(origins) => {
+                #[allow(clippy::mutable_key_type)]
+                let origins = origins
+                    .iter()
+                    .filter_map(|origin| match normalize_origin(origin) {
+                        Ok(origin) => Some(origin),
+                        Err(e) => {
+                            log::warn!("ignoring invalid allowed origin {:?}: {}", origin, e);
+                            None
+                        }
+                    })
+                    .collect();
+                AllowedOrigins::Origins(origins)
             }
-            AllowedOrigins::Origins(origins) => origins.contains(origin),
+            any => any,
         }
     }
+
+    pub(crate) fn origin_allowed(&self, origin: &HeaderValue) -> Result<bool, InvalidOrigin> {
+        if origin == "null" {
+            return Ok(match self {
+                AllowedOrigins::Any { allow_null } => *allow_null,
+                AllowedOrigins::Origins(origins) => origins.contains(origin),
+                AllowedOrigins::Patterns(_) => false,
+                AllowedOrigins::Indexed(index) => index.allows_null(),
+            });
+        }
+
+        // Browsers send origins in their normalized form, which is looked
+        // up as is. Only other forms need to be normalized first.
+        if let AllowedOrigins::Origins(origins) = self {
+            if origins.contains(origin) {
+                return Ok(true);
+            }
+        }
+
+        let parsed = Origin::from_header(origin)?;
+        Ok(match self {
+            AllowedOrigins::Any { .. } => true,
+            AllowedOrigins::Origins(origins) => {
+                let normalized = parsed.to_header_value();
+                normalized != origin && origins.contains(&normalized)
+            }
+            AllowedOrigins::Patterns(patterns) => patterns.iter().any(|p| p.matches(&parsed)),
+            AllowedOrigins::Indexed(index) => index.matches(&parsed),
+        })
+    }
+}
+
+/// The `Origin` of a request. A request has a single origin, several
+/// `Origin` lines are ambiguous and rejected.
+fn single_origin(headers: &HeaderMap) -> Result<Option<&HeaderValue>, InvalidRequest> {
+    let mut origins = headers.get_all(header::ORIGIN).iter();
+    let origin = origins.next();
+    if origins.next().is_some() {
+        return Err(InvalidRequest::InvalidOrigin(InvalidOrigin::Syntax));
+    }
+    Ok(origin)
+}
+
+fn loopback_patterns() -> impl Iterator<Item = OriginPattern> {
+    ["http", "https"]
+        .iter()
+        .map(|scheme| OriginPattern::loopback(scheme))
+}
+
+fn normalize_origin(origin: &HeaderValue) -> Result<HeaderValue, InvalidOrigin> {
+    if origin == "null" {
+        return Ok(origin.clone());
+    }
+    let origin = std::str::from_utf8(origin.as_bytes()).map_err(|_| InvalidOrigin::Encoding)?;
+    Origin::parse(origin).map(|origin| origin.to_header_value())
 }
 
 impl Default for AllowedOrigins {
@@ -42,6 +225,9 @@ impl Default for AllowedOrigins {
     }
 }
 
+/// Collects origins as they are. They are normalized when the
+/// configuration is built, use `AllowedOrigins::try_from_origins` to
+/// validate them immediately.
 impl<A> FromIterator<A> for AllowedOrigins
 where
     A: Into<HeaderValue>,
@@ -58,11 +244,23 @@ where
 
 #[derive(Debug)]
 pub enum InvalidRequest {
+    InvalidOrigin(InvalidOrigin),
     DisallowedOrigin,
     InvalidMethod(method::InvalidMethod),
     DisallowedMethod,
     InvalidHeader(header::InvalidHeaderName),
     DisallowedHeader,
+    /// A cross-site request rejected by the `ResourceIsolation` policy
+    CrossSiteRequest,
+    /// A request without Fetch Metadata rejected by the
+    /// `ResourceIsolation` policy
+    MissingFetchMetadata,
+    /// An unsafe request from a source which is neither same-origin nor
+    /// allowed, rejected by the `CsrfProtection`
+    CsrfCheckFailed,
+    /// A WebSocket handshake from an origin rejected by the
+    /// `WebSocketProtection`
+    DisallowedWebSocketOrigin,
 }
 
 impl error::Error for InvalidRequest {
@@ -71,47 +269,322 @@ impl error::Error for InvalidRequest {
     }
 }
 
+impl InvalidRequest {
+    /// A short, stable identifier for the reason of the rejection
+    pub fn reason(&self) -> &'static str {
+        match self {
+            InvalidRequest::InvalidOrigin(_) => "invalid_origin",
+            InvalidRequest::DisallowedOrigin => "disallowed_origin",
+            InvalidRequest::InvalidMethod(_) => "invalid_method",
+            InvalidRequest::DisallowedMethod => "disallowed_method",
+            InvalidRequest::InvalidHeader(_) => "invalid_header",
+            InvalidRequest::DisallowedHeader => "disallowed_header",
+            InvalidRequest::CrossSiteRequest => "cross_site_request",
+            InvalidRequest::MissingFetchMetadata => "missing_fetch_metadata",
+            InvalidRequest::CsrfCheckFailed => "csrf_check_failed",
+            InvalidRequest::DisallowedWebSocketOrigin => "disallowed_websocket_origin",
+        }
+    }
+}
+
 impl fmt::Display for InvalidRequest {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt::Debug::fmt(self, f)
     }
 }
 
+/// The settings of the CORS layer, built by `CorsBuilder::into_config`.
+///
+/// The fields which shape the response headers are private and only set by
+/// the builder, since the headers are precomputed from them. The allowed
+/// methods and headers can be read with `allowed_methods` and
+/// `allowed_headers`.
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub allowed_methods: HashSet<Method>,
-    pub allowed_methods_header: HeaderValue,
+    pub(crate) allowed_methods: MethodSet,
+    pub(crate) allowed_methods_header: HeaderValue,
     pub allowed_origins: AllowedOrigins,
-    pub allowed_headers: HashSet<HeaderName>,
-    pub allowed_headers_header: HeaderValue,
-    pub allow_credentials: bool,
-    pub exposed_headers_header: Option<HeaderValue>,
-    pub max_age: Option<HeaderValue>,
-    pub prefer_wildcard: bool,
-    pub vary_header: HeaderValue,
+    pub(crate) allowed_headers: HashSet<HeaderName>,
+    pub(crate) allowed_headers_header: HeaderValue,
+    pub(crate) allow_credentials: bool,
+    pub csrf_protection: Option<CsrfProtection>,
+    pub denial_level: log::Level,
+    pub(crate) exposed_headers_header: Option<HeaderValue>,
+    pub(crate) max_age: Option<HeaderValue>,
+    #[cfg(feature = "metrics")]
+    pub metrics: Option<CorsMetrics>,
+    pub(crate) prefer_wildcard: bool,
+    pub report_only: Option<ReportOnly>,
+    pub route_methods: Option<RouteMethods>,
+    pub resource_isolation: Option<ResourceIsolation>,
+    pub same_origin: Option<SameOrigin>,
+    pub(crate) timing_allow_origin: Option<TimingAllowOrigin>,
+    pub(crate) vary_header: HeaderValue,
+    pub websocket: Option<WebSocketProtection>,
+    pub(crate) templates: HeaderTemplates,
 }
 
+/// The `Timing-Allow-Origin` header, which exposes the detailed Resource
+/// Timing of a response to the requesting origin
+///
+/// https://w3c.github.io/resource-timing/#sec-timing-allow-origin
+pub const TIMING_ALLOW_ORIGIN: HeaderName = HeaderName::from_static("timing-allow-origin");
+
+/// How `Timing-Allow-Origin` is sent on actual responses to allowed origins
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingAllowOrigin {
+    /// Send `*`
+    Wildcard,
+    /// Send the request origin
+    Reflect,
+}
+
+/// Response headers precomputed per response shape by
+/// `CorsBuilder::into_config`, so that a request only clones one prebuilt
+/// map and at most inserts the reflected origin.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HeaderTemplates {
+    no_origin: HeaderMap,
+    simple: HeaderMap,
+    preflight: HeaderMap,
+    /// Whether the request origin is reflected in
+    /// `Access-Control-Allow-Origin` rather than the wildcard
+    reflect_origin: bool,
+    /// Whether the request origin is reflected in `Timing-Allow-Origin`
+    reflect_timing: bool,
+}
+
+impl HeaderTemplates {
+    pub(crate) fn new(config: &Config) -> Self {
+        let mut no_origin = HeaderMap::new();
+        no_origin.insert(header::VARY, config.vary_header.clone());
+
+        let reflect_origin = config.allow_credentials || !config.prefer_wildcard;
+
+        let mut common = no_origin.clone();
+        // When reflecting, the placeholder is replaced in place by the request
+        // origin, which avoids growing the cloned map
+        common.insert(
+            header::ACCESS_CONTROL_ALLOW_ORIGIN,
+            HeaderValue::from_static("*"),
+        );
+        if config.allow_credentials {
+            common.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+
+        let mut simple = common.clone();
+        if let Some(ref exposed_headers) = config.exposed_headers_header {
+            simple.insert(
+                header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                exposed_headers.clone(),
+            );
+        }
+
+        if config.timing_allow_origin.is_some() {
+            // A reflected origin replaces the placeholder, as for
+            // `Access-Control-Allow-Origin`
+            simple.insert(TIMING_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+        }
+
+        let mut preflight = common;
+        preflight.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            config.allowed_methods_header.clone(),
+        );
+        preflight.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            config.allowed_headers_header.clone(),
+        );
+        if let Some(ref max_age) = config.max_age {
+            preflight.insert(header::ACCESS_CONTROL_MAX_AGE, max_age.clone());
+        }
+
+        HeaderTemplates {
+            no_origin,
+            simple,
+            preflight,
+            reflect_origin,
+            reflect_timing: config.timing_allow_origin == Some(TimingAllowOrigin::Reflect),
+        }
+    }
+
+    fn with_origin(&self, template: &HeaderMap, origin: &HeaderValue) -> HeaderMap {
+        let mut headers = template.clone();
+        if self.reflect_origin {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+        }
+        if self.reflect_timing {
+            if let Some(value) = headers.get_mut(TIMING_ALLOW_ORIGIN) {
+                *value = origin.clone();
+            }
+        }
+        headers
+    }
+}
+
+/// How to answer a request which passed the checks.
+///
+/// `Options` was added with `CorsBuilder::route_methods`; exhaustive
+/// matches need an arm for it.
 #[derive(Debug)]
 pub enum CorsResource {
     Preflight(HeaderMap),
     Simple(HeaderMap),
+    /// A non-CORS `OPTIONS` request to a route of the `RouteMethods`,
+    /// answered with `204 No Content` and an `Allow` header
+    Options(HeaderMap),
+}
+
+impl CorsResource {
+    /// The CORS headers to set on the response
+    pub fn headers(&self) -> &HeaderMap {
+        match self {
+            CorsResource::Preflight(headers)
+            | CorsResource::Simple(headers)
+            | CorsResource::Options(headers) => headers,
+        }
+    }
+
+    pub fn into_headers(self) -> HeaderMap {
+        match self {
+            CorsResource::Preflight(headers)
+            | CorsResource::Simple(headers)
+            | CorsResource::Options(headers) => headers,
+        }
+    }
+
+    pub fn is_preflight(&self) -> bool {
+        matches!(self, CorsResource::Preflight(_))
+    }
+
+    /// Whether the request is answered by the CORS layer itself with
+    /// `204 No Content`, rather than being forwarded to the handler
+    pub fn is_answered_locally(&self) -> bool {
+        matches!(self, CorsResource::Preflight(_) | CorsResource::Options(_))
+    }
+}
+
+/// The kind of request as seen by the CORS processing model
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestKind {
+    /// The request carries no `Origin` header, so it is not a CORS request
+    NoOrigin,
+    /// An `OPTIONS` request carrying `Access-Control-Request-Method`
+    Preflight,
+    /// Any other request carrying an `Origin` header
+    Simple,
+}
+
+impl RequestKind {
+    pub fn of<B>(request: &Request<B>) -> RequestKind {
+        let headers = request.headers();
+        if !headers.contains_key(header::ORIGIN) {
+            RequestKind::NoOrigin
+        } else if request.method() == Method::OPTIONS
+            && headers.contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+        {
+            RequestKind::Preflight
+        } else {
+            RequestKind::Simple
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestKind::NoOrigin => "no_origin",
+            RequestKind::Preflight => "preflight",
+            RequestKind::Simple => "simple",
+        }
+    }
 }
 
 impl Config {
+    /// The methods preflights may request, for paths without a route of
+    /// `route_methods`
+    pub fn allowed_methods(&self) -> &MethodSet {
+        &self.allowed_methods
+    }
+
+    /// The headers preflights may request
+    pub fn allowed_headers(&self) -> &HashSet<HeaderName> {
+        &self.allowed_headers
+    }
+
     // https://www.w3.org/TR/cors/#resource-processing-model
     pub fn process_request<B>(&self, request: &Request<B>) -> Result<CorsResource, InvalidRequest> {
+        let kind = RequestKind::of(request);
+
+        #[cfg(feature = "tracing")]
+        let span = trace::request_span(request, kind);
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+
+        let result = self.evaluate(request, kind);
+
+        #[cfg(feature = "tracing")]
+        trace::record_outcome(&span, &result);
+
+        if let Some(ref report_only) = self.report_only {
+            report_only.check(request, &result);
+        }
+
+        #[cfg(feature = "metrics")]
+        if let Some(ref metrics) = self.metrics {
+            metrics.record(request, &result);
+        }
+
+        if let Err(ref e) = result {
+            self.report_denial(request, e);
+        }
+
+        result
+    }
+
+    pub(crate) fn evaluate<B>(
+        &self,
+        request: &Request<B>,
+        kind: RequestKind,
+    ) -> Result<CorsResource, InvalidRequest> {
         use self::InvalidRequest::*;
 
-        let origin = request.headers().get(header::ORIGIN);
+        let origin = single_origin(request.headers())?;
         let requested_method = request.headers().get(header::ACCESS_CONTROL_REQUEST_METHOD);
 
-        match (origin, request.method(), requested_method) {
-            (None, _, _) => {
-                // Without an origin, this cannot be a CORS request
-                let headers = self.basic_headers();
-                Ok(CorsResource::Simple(headers))
+        if let Some(ref websocket) = self.websocket {
+            if is_websocket_upgrade(request) {
+                return self.check_websocket(request, websocket);
+            }
+        }
+
+        if let Some(ref isolation) = self.resource_isolation {
+            match isolation.check(request) {
+                Isolation::Allowed => {}
+                Isolation::CrossSite => match origin {
+                    Some(origin) if self.allowed_origins.origin_allowed(origin) == Ok(true) => {}
+                    _ => return Err(CrossSiteRequest),
+                },
+                Isolation::Denied => return Err(MissingFetchMetadata),
+            }
+        }
+
+        if let Some(ref csrf) = self.csrf_protection {
+            if csrf.applies_to(request) {
+                self.check_source(request, csrf)?;
+            }
+        }
+
+        if let (Some(same_origin), Some(origin)) = (self.same_origin_detection(), origin) {
+            if Origin::from_header(origin).is_ok_and(|o| same_origin.is_same_origin(request, &o)) {
+                // Not a cross-origin request, only Vary is sent for caches
+                return Ok(CorsResource::Simple(self.templates.no_origin.clone()));
             }
-            (Some(origin), &Method::OPTIONS, Some(requested_method)) => {
+        }
+
+        match (kind, origin, requested_method) {
+            (RequestKind::Preflight, Some(origin), Some(requested_method)) => {
                 // Preflight request
                 // https://www.w3.org/TR/cors/#resource-preflight-requests
 
@@ -120,28 +593,32 @@ impl Config {
                     origin,
                     requested_method
                 );
-                if !self.allowed_origins.origin_allowed(origin) {
-                    return Err(DisallowedOrigin);
-                }
+                self.check_origin(origin)?;
 
                 let requested_method =
                     Method::from_bytes(requested_method.as_bytes()).map_err(InvalidMethod)?;
 
-                if !self.allowed_methods.contains(&requested_method) {
+                let route = self.find_route(request);
+                let method_allowed = match route {
+                    Some(route) => route.methods().contains(&requested_method),
+                    None => self.allowed_methods.contains(&requested_method),
+                };
+                if !method_allowed {
                     return Err(DisallowedMethod);
                 }
 
-                let requested_headers: Result<HashSet<_>, _> = match request
+                // A list over all lines of the header, with optional
+                // whitespace around its items
+                // https://httpwg.org/specs/rfc9110.html#abnf.extension
+                let requested_headers: Result<HashSet<_>, _> = request
                     .headers()
-                    .get(header::ACCESS_CONTROL_REQUEST_HEADERS)
-                {
-                    Some(headers) => headers
-                        .as_bytes()
-                        .split(|&b| b == b',')
-                        .map(HeaderName::from_bytes)
-                        .collect(),
-                    None => Ok(Default::default()),
-                };
+                    .get_all(header::ACCESS_CONTROL_REQUEST_HEADERS)
+                    .iter()
+                    .flat_map(|headers| headers.as_bytes().split(|&b| b == b','))
+                    .map(<[u8]>::trim_ascii)
+                    .filter(|name| !name.is_empty())
+                    .map(HeaderName::from_bytes)
+                    .collect();
 
                 #[allow(clippy::mutable_key_type)]
                 let requested_headers = requested_headers.map_err(InvalidHeader)?;
@@ -154,72 +631,133 @@ impl Config {
 
                 // All checks complete; generate response
 
-                let mut headers = self.common_headers(origin.clone());
-
-                headers.insert(
-                    header::ACCESS_CONTROL_ALLOW_METHODS,
-                    self.allowed_methods_header.clone(),
-                );
-
-                headers.insert(
-                    header::ACCESS_CONTROL_ALLOW_HEADERS,
-                    self.allowed_headers_header.clone(),
-                );
-
-                if let Some(ref max_age) = self.max_age {
-                    headers.insert(header::ACCESS_CONTROL_MAX_AGE, max_age.clone());
+                let mut headers = self
+                    .templates
+                    .with_origin(&self.templates.preflight, origin);
+                if let Some(route) = route {
+                    headers.insert(
+                        header::ACCESS_CONTROL_ALLOW_METHODS,
+                        route.allow_methods_header().clone(),
+                    );
                 }
-
                 Ok(CorsResource::Preflight(headers))
             }
-            (Some(origin), _, _) => {
+            (RequestKind::Simple, Some(origin), _) => {
                 // Simple / Actual request
                 // https://www.w3.org/TR/cors/#resource-requests
 
-                log::debug!("acrtual request origin:{:?}", origin);
-                if !self.allowed_origins.origin_allowed(origin) {
-                    return Err(DisallowedOrigin);
+                log::debug!("actual request origin:{:?}", origin);
+                self.check_origin(origin)?;
+
+                let headers = self.templates.with_origin(&self.templates.simple, origin);
+                Ok(CorsResource::Simple(headers))
+            }
+            (RequestKind::NoOrigin, _, _) if request.method() == Method::OPTIONS => {
+                // A plain OPTIONS request is answered for the routes which
+                // do not handle OPTIONS themselves
+                // https://httpwg.org/specs/rfc9110.html#OPTIONS
+                match self.find_route(request) {
+                    Some(route) if !route.methods().contains(&Method::OPTIONS) => {
+                        let mut headers = self.templates.no_origin.clone();
+                        headers.insert(header::ALLOW, route.allow_header().clone());
+                        Ok(CorsResource::Options(headers))
+                    }
+                    _ => Ok(CorsResource::Simple(self.templates.no_origin.clone())),
                 }
+            }
+            _ => {
+                // Without an origin, this cannot be a CORS request
+                Ok(CorsResource::Simple(self.templates.no_origin.clone()))
+            }
+        }
+    }
 
-                let mut headers = self.common_headers(origin.clone());
+    /// The configured same-origin detection, or the `Host` based one the
+    /// CSRF protection falls back to, so that a request trusted as
+    /// same-origin by the CSRF check is not rejected as cross-origin
+    fn same_origin_detection(&self) -> Option<&SameOrigin> {
+        match (&self.same_origin, &self.csrf_protection) {
+            (Some(same_origin), _) => Some(same_origin),
+            (None, Some(_)) => Some(&HOST_ONLY),
+            (None, None) => None,
+        }
+    }
 
-                if let Some(ref exposed_headers) = self.exposed_headers_header {
-                    headers.insert(
-                        header::ACCESS_CONTROL_EXPOSE_HEADERS,
-                        exposed_headers.clone(),
-                    );
-                }
+    fn find_route<B>(&self, request: &Request<B>) -> Option<RouteMatch<'_>> {
+        self.route_methods.as_ref()?.find(request.uri().path())
+    }
 
-                Ok(CorsResource::Simple(headers))
+    /// WebSocket handshakes are not CORS requests: they are only checked
+    /// against the origin policy and receive no CORS headers
+    fn check_websocket<B>(
+        &self,
+        request: &Request<B>,
+        websocket: &WebSocketProtection,
+    ) -> Result<CorsResource, InvalidRequest> {
+        let allowed = match request.headers().get(header::ORIGIN) {
+            Some(origin) => {
+                let allowed_origins = websocket.allowed_origins().unwrap_or(&self.allowed_origins);
+                let same_origin = self.same_origin.as_ref().unwrap_or(&HOST_ONLY);
+                allowed_origins.origin_allowed(origin) == Ok(true)
+                    || Origin::from_header(origin)
+                        .is_ok_and(|origin| same_origin.is_same_origin(request, &origin))
             }
+            None => websocket.allows_missing_origin(),
+        };
+
+        if allowed {
+            Ok(CorsResource::Simple(self.templates.no_origin.clone()))
+        } else {
+            Err(InvalidRequest::DisallowedWebSocketOrigin)
         }
     }
 
-    fn common_headers(&self, origin: HeaderValue) -> HeaderMap {
-        let mut headers = self.basic_headers();
-
-        if self.allow_credentials {
-            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    fn check_source<B>(
+        &self,
+        request: &Request<B>,
+        csrf: &CsrfProtection,
+    ) -> Result<(), InvalidRequest> {
+        let same_origin = self.same_origin.as_ref().unwrap_or(&HOST_ONLY);
+        let trusted = match RequestSource::of(request) {
+            RequestSource::Origin(origin) => {
+                self.allowed_origins.origin_allowed(origin) == Ok(true)
+                    || Origin::from_header(origin)
+                        .is_ok_and(|origin| same_origin.is_same_origin(request, &origin))
+            }
+            RequestSource::Referer(origin) => {
+                same_origin.is_same_origin(request, &origin)
+                    || self
+                        .allowed_origins
+                        .origin_allowed(&origin.to_header_value())
+                        == Ok(true)
+            }
+            RequestSource::Missing => csrf.allows_missing_source(),
+        };
 
-            headers.insert(
-                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
-                HeaderValue::from_static("true"),
-            );
+        if trusted {
+            Ok(())
         } else {
-            let allowed_origin = if self.prefer_wildcard {
-                HeaderValue::from_static("*")
-            } else {
-                origin
-            };
-            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allowed_origin);
+            Err(InvalidRequest::CsrfCheckFailed)
         }
+    }
 
-        headers
+    fn check_origin(&self, origin: &HeaderValue) -> Result<(), InvalidRequest> {
+        match self.allowed_origins.origin_allowed(origin) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(InvalidRequest::DisallowedOrigin),
+            Err(e) => Err(InvalidRequest::InvalidOrigin(e)),
+        }
     }
 
-    fn basic_headers(&self) -> HeaderMap {
-        let mut headers = HeaderMap::new();
-        headers.insert(header::VARY, self.vary_header.clone());
-        headers
+    fn report_denial<B>(&self, request: &Request<B>, e: &InvalidRequest) {
+        #[cfg(feature = "tracing")]
+        trace::denial_event(self.denial_level, request, e);
+
+        log::log!(
+            self.denial_level,
+            "CORS request to {} is denied: {:?}",
+            request.uri(),
+            e
+        );
     }
 }
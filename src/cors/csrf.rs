@@ -0,0 +1,75 @@
+//! Origin-based CSRF protection for state-changing requests
+//!
+//! Simple cross-site requests such as form posts are sent without a
+//! preflight, with the cookies of the user. For unsafe methods the source
+//! of the request is therefore checked: the `Origin` header, or the
+//! `Referer` header when the origin is absent, has to be same-origin or
+//! allowed.
+//!
+//! https://cheatsheetseries.owasp.org/cheatsheets/Cross-Site_Request_Forgery_Prevention_Cheat_Sheet.html#verifying-origin-with-standard-headers
+
+use super::origin::{url_origin, Origin};
+
+use http::{header, HeaderValue, Method, Request};
+
+#[derive(Debug, Clone, Default)]
+pub struct CsrfProtection {
+    allow_missing_source: bool,
+}
+
+/// Where the source of a request was taken from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestSource<'a> {
+    Origin(&'a HeaderValue),
+    /// The origin of the `Referer`, when there is no `Origin` header
+    Referer(Origin),
+    Missing,
+}
+
+impl CsrfProtection {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Whether unsafe requests without `Origin` and `Referer`, e.g. from
+    /// clients other than browsers, are allowed. Defaults to `false`.
+    pub fn allow_missing_source(mut self, allow_missing_source: bool) -> Self {
+        self.allow_missing_source = allow_missing_source;
+        self
+    }
+
+    pub fn allows_missing_source(&self) -> bool {
+        self.allow_missing_source
+    }
+
+    /// Whether the request has to be checked, i.e. whether its method is
+    /// unsafe
+    pub fn applies_to<B>(&self, request: &Request<B>) -> bool {
+        !is_safe(request.method())
+    }
+}
+
+/// The methods which are defined as safe, i.e. which must not change state
+/// https://httpwg.org/specs/rfc9110.html#safe.methods
+fn is_safe(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
+
+impl RequestSource<'_> {
+    pub fn of<B>(request: &Request<B>) -> RequestSource<'_> {
+        let headers = request.headers();
+        if let Some(origin) = headers.get(header::ORIGIN) {
+            return RequestSource::Origin(origin);
+        }
+        match headers
+            .get(header::REFERER)
+            .and_then(|referer| url_origin(referer.to_str().ok()?))
+        {
+            Some(origin) => RequestSource::Referer(origin),
+            None => RequestSource::Missing,
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Resource isolation based on Fetch Metadata request headers
+//!
+//! Browsers describe the context of every request in `Sec-Fetch-Site`,
+//! `Sec-Fetch-Mode` and `Sec-Fetch-Dest`. This allows to reject cross-site
+//! requests which never reach CORS, such as cross-site form posts or image
+//! loads, while still allowing links to the site to be followed.
+//!
+//! https://w3c.github.io/webappsec-fetch-metadata/
+//! https://web.dev/articles/fetch-metadata
+
+use http::{header::HeaderName, Method, Request};
+
+pub const SEC_FETCH_SITE: HeaderName = HeaderName::from_static("sec-fetch-site");
+pub const SEC_FETCH_MODE: HeaderName = HeaderName::from_static("sec-fetch-mode");
+pub const SEC_FETCH_DEST: HeaderName = HeaderName::from_static("sec-fetch-dest");
+
+/// How requests without Fetch Metadata are handled, i.e. requests from
+/// older browsers and from clients other than browsers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissingMetadata {
+    #[default]
+    Allow,
+    Deny,
+}
+
+/// The outcome of `ResourceIsolation::check`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Isolation {
+    /// The request is not cross-site, or exempt
+    Allowed,
+    /// The request is cross-site and only allowed if its origin passes the
+    /// CORS allowlist
+    CrossSite,
+    Denied,
+}
+
+/// A resource isolation policy: cross-site requests are rejected unless
+/// they are navigations or their origin passes the CORS allowlist.
+#[derive(Debug, Clone)]
+pub struct ResourceIsolation {
+    exempt_paths: Vec<String>,
+    allow_navigations: bool,
+    missing_metadata: MissingMetadata,
+}
+
+impl Default for ResourceIsolation {
+    fn default() -> Self {
+        ResourceIsolation {
+            exempt_paths: Vec::new(),
+            allow_navigations: true,
+            missing_metadata: MissingMetadata::Allow,
+        }
+    }
+}
+
+impl ResourceIsolation {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Exempt the path and every path below it from the policy, e.g. for
+    /// endpoints which are meant to be embedded or called cross-site
+    pub fn exempt_path(mut self, path: impl Into<String>) -> Self {
+        let path = path.into();
+        self.exempt_paths
+            .push(path.strip_suffix('/').unwrap_or(&path).to_string());
+        self
+    }
+
+    /// Whether cross-site top-level navigations, e.g. following a link to
+    /// the site, are allowed. Defaults to `true`.
+    pub fn allow_navigations(mut self, allow_navigations: bool) -> Self {
+        self.allow_navigations = allow_navigations;
+        self
+    }
+
+    /// How requests without `Sec-Fetch-Site` are handled. Defaults to
+    /// `MissingMetadata::Allow`.
+    pub fn missing_metadata(mut self, missing_metadata: MissingMetadata) -> Self {
+        self.missing_metadata = missing_metadata;
+        self
+    }
+
+    pub fn check<B>(&self, request: &Request<B>) -> Isolation {
+        if self.is_exempt(request.uri().path()) {
+            return Isolation::Allowed;
+        }
+
+        let headers = request.headers();
+        let site = match headers.get(SEC_FETCH_SITE) {
+            Some(site) => site,
+            None => {
+                return match self.missing_metadata {
+                    MissingMetadata::Allow => Isolation::Allowed,
+                    MissingMetadata::Deny => Isolation::Denied,
+                }
+            }
+        };
+        // `none` is a user initiated request, e.g. a bookmark
+        if site == "same-origin" || site == "same-site" || site == "none" {
+            return Isolation::Allowed;
+        }
+
+        if self.allow_navigations && is_navigation(request) {
+            return Isolation::Allowed;
+        }
+
+        Isolation::CrossSite
+    }
+
+    fn is_exempt(&self, path: &str) -> bool {
+        self.exempt_paths.iter().any(|exempt| {
+            path.strip_prefix(exempt.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+}
+
+/// A top-level navigation with a safe method. Navigations which load the
+/// resource into `<object>` or `<embed>` are embeddings.
+fn is_navigation<B>(request: &Request<B>) -> bool {
+    let headers = request.headers();
+    let navigate = headers
+        .get(SEC_FETCH_MODE)
+        .is_some_and(|mode| mode == "navigate");
+    let embedded = headers
+        .get(SEC_FETCH_DEST)
+        .is_some_and(|dest| dest == "object" || dest == "embed");
+    navigate && !embedded && (request.method() == Method::GET || request.method() == Method::HEAD)
+}
@@ -0,0 +1,119 @@
+//! An indexed matcher for allowlists of many origins and patterns
+//!
+//! Exact origins are looked up in a hash set. Patterns are indexed by their
+//! host: exact hosts in a hash map, subdomain suffixes in a trie over the
+//! reversed host labels, so that `*.corp.example` is stored under
+//! `example` → `corp`. A lookup therefore costs one hash lookup per label of
+//! the request host, independent of the number of entries. Only patterns on
+//! any host or on loopback hosts are matched one by one.
+
+use super::origin::{InvalidOrigin, Origin};
+use super::pattern::{HostPattern, OriginPattern};
+
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Default)]
+pub struct OriginIndex {
+    allow_null: bool,
+    origins: HashSet<Origin>,
+    hosts: HashMap<String, Vec<OriginPattern>>,
+    subdomains: LabelTrie,
+    unindexed: Vec<OriginPattern>,
+}
+
+/// A node of the trie over reversed host labels, holding the patterns which
+/// match the strict subdomains of the host spelled by the path to it
+#[derive(Debug, Clone, Default)]
+struct LabelTrie {
+    children: HashMap<String, LabelTrie>,
+    patterns: Vec<OriginPattern>,
+}
+
+impl OriginIndex {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Allow a serialized origin, or the opaque origin `null`
+    pub fn insert_origin(&mut self, origin: &str) -> Result<(), InvalidOrigin> {
+        if origin == "null" {
+            self.allow_null = true;
+        } else {
+            self.origins.insert(Origin::parse(origin)?);
+        }
+        Ok(())
+    }
+
+    /// Allow every origin matching the pattern, see `OriginPattern`
+    pub fn insert_pattern(&mut self, pattern: &str) -> Result<(), InvalidOrigin> {
+        self.add_pattern(OriginPattern::parse(pattern)?);
+        Ok(())
+    }
+
+    pub fn add_pattern(&mut self, pattern: OriginPattern) {
+        match pattern.host {
+            HostPattern::Exact(ref host) => {
+                self.hosts.entry(host.clone()).or_default().push(pattern)
+            }
+            HostPattern::Subdomains(ref suffix) => {
+                let node = suffix
+                    .rsplit('.')
+                    .fold(&mut self.subdomains, |node, label| {
+                        node.children.entry(label.to_string()).or_default()
+                    });
+                node.patterns.push(pattern);
+            }
+            HostPattern::Any | HostPattern::Loopback => self.unindexed.push(pattern),
+        }
+    }
+
+    pub fn allows_null(&self) -> bool {
+        self.allow_null
+    }
+
+    /// The number of origins and patterns, not counting `null`
+    pub fn len(&self) -> usize {
+        self.origins.len()
+            + self.hosts.values().map(Vec::len).sum::<usize>()
+            + self.subdomains.len()
+            + self.unindexed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.allow_null && self.len() == 0
+    }
+
+    pub fn matches(&self, origin: &Origin) -> bool {
+        if self.origins.contains(origin) {
+            return true;
+        }
+
+        let host = origin.host();
+        if let Some(patterns) = self.hosts.get(host) {
+            if patterns.iter().any(|p| p.matches(origin)) {
+                return true;
+            }
+        }
+
+        let mut node = &self.subdomains;
+        let mut labels = host.rsplit('.').peekable();
+        while let Some(label) = labels.next() {
+            node = match node.children.get(label) {
+                Some(node) => node,
+                None => break,
+            };
+            // The host has to be a strict subdomain of the suffix
+            if labels.peek().is_some() && node.patterns.iter().any(|p| p.matches(origin)) {
+                return true;
+            }
+        }
+
+        self.unindexed.iter().any(|p| p.matches(origin))
+    }
+}
+
+impl LabelTrie {
+    fn len(&self) -> usize {
+        self.patterns.len() + self.children.values().map(LabelTrie::len).sum::<usize>()
+    }
+}
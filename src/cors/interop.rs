@@ -0,0 +1,295 @@
+//! Conversions between this crate's configuration and
+//! `tower_http::cors::CorsLayer`, enabled by the `tower-http` feature.
+//!
+//! Both implementations do not behave identically. Every conversion
+//! returns the semantic differences it could not bridge instead of silently
+//! approximating them.
+
+use super::builder::CorsBuilder;
+use super::config::{AllowedOrigins, Config};
+
+use futures_util::FutureExt;
+use http::{header, HeaderName, HeaderValue, Method, Request, Response};
+use std::{
+    convert::Infallible,
+    future::{ready, Ready},
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::{Layer, Service};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// A behavior which differs between both implementations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    /// tower-http never rejects a request with `403 Forbidden`: disallowed
+    /// requests reach the handler, their responses just lack the CORS
+    /// headers.
+    NoRejection,
+
+    /// tower-http answers every `OPTIONS` request itself with `200 OK`,
+    /// including those which are not preflights.
+    PreflightHandling,
+
+    /// tower-http sends `Access-Control-Allow-Origin: *` to every origin,
+    /// including the `null` origin this configuration denies.
+    WildcardAllowsNullOrigin,
+
+    /// tower-http cannot combine an origin allowlist with a wildcard
+    /// response, the request origin is reflected instead.
+    ReflectedInsteadOfWildcard,
+
+    /// The setting has no tower-http counterpart and is dropped
+    Unsupported(&'static str),
+
+    /// The allowed origins of the tower-http layer could not be determined,
+    /// the converted configuration denies every origin.
+    UnknownOrigins,
+
+    /// tower-http mirrors or wildcards the requested methods, the converted
+    /// configuration allows no method.
+    UnrepresentableMethods,
+
+    /// tower-http mirrors or wildcards the requested headers, the converted
+    /// configuration allows no header.
+    UnrepresentableHeaders,
+
+    /// tower-http exposes every header with `*`, the converted
+    /// configuration exposes none.
+    UnrepresentableExposedHeaders,
+}
+
+/// The result of a conversion along with the differences to the source
+#[derive(Debug)]
+pub struct Conversion<T> {
+    pub value: T,
+    pub differences: Vec<Difference>,
+}
+
+impl<T> Conversion<T> {
+    pub fn is_exact(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+impl Config {
+    /// Build a tower-http `CorsLayer` which behaves like this configuration
+    /// as closely as possible
+    pub fn to_tower_http(&self) -> Conversion<CorsLayer> {
+        let mut differences = vec![Difference::NoRejection, Difference::PreflightHandling];
+
+        let allow_origin = match self.allowed_origins {
+            AllowedOrigins::Any { allow_null }
+                if self.prefer_wildcard && !self.allow_credentials =>
+            {
+                if !allow_null {
+                    differences.push(Difference::WildcardAllowsNullOrigin);
+                }
+                AllowOrigin::any()
+            }
+            ref allowed_origins => {
+                if self.prefer_wildcard && !self.allow_credentials {
+                    differences.push(Difference::ReflectedInsteadOfWildcard);
+                }
+                // Evaluate with this crate's matcher to keep normalization
+                let allowed_origins = Arc::new(allowed_origins.clone());
+                AllowOrigin::predicate(move |origin, _| {
+                    allowed_origins.origin_allowed(origin).unwrap_or(false)
+                })
+            }
+        };
+
+        let mut layer = CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods(self.allowed_methods.iter().cloned().collect::<Vec<_>>())
+            .allow_headers(self.allowed_headers.iter().cloned().collect::<Vec<_>>())
+            .allow_credentials(self.allow_credentials);
+
+        if let Some(ref exposed) = self.exposed_headers_header {
+            layer = layer.expose_headers(split_header_names(exposed));
+        }
+        if let Some(max_age) = self.max_age.as_ref().and_then(parse_seconds) {
+            layer = layer.max_age(max_age);
+        }
+
+        if self.csrf_protection.is_some() {
+            differences.push(Difference::Unsupported("csrf_protection"));
+        }
+        if self.resource_isolation.is_some() {
+            differences.push(Difference::Unsupported("resource_isolation"));
+        }
+        if self.route_methods.is_some() {
+            differences.push(Difference::Unsupported("route_methods"));
+        }
+        if self.same_origin.is_some() {
+            differences.push(Difference::Unsupported("same_origin"));
+        }
+        if self.timing_allow_origin.is_some() {
+            differences.push(Difference::Unsupported("timing_allow_origin"));
+        }
+        if self.websocket.is_some() {
+            differences.push(Difference::Unsupported("websocket"));
+        }
+        if self.report_only.is_some() {
+            differences.push(Difference::Unsupported("report_only"));
+        }
+        #[cfg(feature = "metrics")]
+        if self.metrics.is_some() {
+            differences.push(Difference::Unsupported("metrics"));
+        }
+
+        Conversion {
+            value: layer,
+            differences,
+        }
+    }
+}
+
+impl CorsBuilder {
+    /// Reconstruct a builder from a tower-http `CorsLayer`.
+    ///
+    /// tower-http does not expose its configuration, so the layer is probed
+    /// with synthetic requests and the configuration is read back from the
+    /// responses. Only what is observable this way can be converted.
+    pub fn from_tower_http(layer: &CorsLayer) -> Conversion<CorsBuilder> {
+        let mut differences = vec![Difference::NoRejection, Difference::PreflightHandling];
+        let mut builder = CorsBuilder::new();
+
+        let preflight = probe(
+            layer,
+            Request::builder()
+                .method(Method::OPTIONS)
+                .header(header::ORIGIN, PROBE_ORIGIN)
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, PROBE_METHOD)
+                .header(header::ACCESS_CONTROL_REQUEST_HEADERS, PROBE_HEADER),
+        );
+        let headers = preflight.headers();
+
+        match headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN) {
+            Some(origin) if origin == "*" => {
+                builder = builder
+                    .allow_origins(AllowedOrigins::Any { allow_null: true })
+                    .prefer_wildcard(true);
+            }
+            Some(origin) if origin == PROBE_ORIGIN => {
+                let null = probe(
+                    layer,
+                    Request::builder()
+                        .method(Method::OPTIONS)
+                        .header(header::ORIGIN, "null")
+                        .header(header::ACCESS_CONTROL_REQUEST_METHOD, PROBE_METHOD),
+                );
+                let allow_null = null.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                    == Some(&HeaderValue::from_static("null"));
+                builder = builder.allow_origins(AllowedOrigins::Any { allow_null });
+            }
+            _ => differences.push(Difference::UnknownOrigins),
+        }
+
+        if headers.get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+            == Some(&HeaderValue::from_static("true"))
+        {
+            builder = builder.allow_credentials(true);
+        }
+
+        if let Some(methods) = headers.get(header::ACCESS_CONTROL_ALLOW_METHODS) {
+            if methods == "*" || methods == PROBE_METHOD {
+                differences.push(Difference::UnrepresentableMethods);
+            } else {
+                let methods =
+                    split_values(methods).filter_map(|m| Method::from_bytes(m.as_bytes()).ok());
+                builder = builder.allow_methods(methods.collect::<Vec<_>>());
+            }
+        }
+
+        if let Some(allowed) = headers.get(header::ACCESS_CONTROL_ALLOW_HEADERS) {
+            if allowed == "*" || allowed == PROBE_HEADER {
+                differences.push(Difference::UnrepresentableHeaders);
+            } else {
+                builder = builder.allow_headers(split_header_names(allowed));
+            }
+        }
+
+        if let Some(max_age) = headers
+            .get(header::ACCESS_CONTROL_MAX_AGE)
+            .and_then(parse_seconds)
+        {
+            builder = builder.max_age(max_age);
+        }
+
+        let simple = probe(
+            layer,
+            Request::builder().header(header::ORIGIN, PROBE_ORIGIN),
+        );
+        if let Some(exposed) = simple.headers().get(header::ACCESS_CONTROL_EXPOSE_HEADERS) {
+            if exposed == "*" {
+                differences.push(Difference::UnrepresentableExposedHeaders);
+            } else {
+                builder = builder.expose_headers(split_header_names(exposed));
+            }
+        }
+
+        Conversion {
+            value: builder,
+            differences,
+        }
+    }
+}
+
+const PROBE_ORIGIN: &str = "https://probe.invalid";
+const PROBE_METHOD: &str = "X-PROBE";
+const PROBE_HEADER: &str = "x-probe";
+
+/// An inner service which answers every request with an empty response
+struct Probe;
+
+impl Service<Request<()>> for Probe {
+    type Response = Response<()>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: Request<()>) -> Self::Future {
+        ready(Ok(Response::new(())))
+    }
+}
+
+fn probe(layer: &CorsLayer, request: http::request::Builder) -> Response<()> {
+    let request = request.body(()).expect("valid probe request");
+    let mut service = layer.layer(Probe);
+    let ready = service.poll_ready(&mut Context::from_waker(
+        futures_util::task::noop_waker_ref(),
+    ));
+    assert!(
+        ready.is_ready(),
+        "tower-http Cors is ready when its inner service is"
+    );
+    match service.call(request).now_or_never() {
+        Some(Ok(response)) => response,
+        Some(Err(e)) => match e {},
+        None => unreachable!("tower-http Cors completes with its inner service"),
+    }
+}
+
+fn split_values(value: &HeaderValue) -> impl Iterator<Item = &str> {
+    value
+        .to_str()
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+}
+
+fn split_header_names(value: &HeaderValue) -> Vec<HeaderName> {
+    split_values(value)
+        .filter_map(|name| HeaderName::from_bytes(name.as_bytes()).ok())
+        .collect()
+}
+
+fn parse_seconds(value: &HeaderValue) -> Option<Duration> {
+    value.to_str().ok()?.parse().ok().map(Duration::from_secs)
+}
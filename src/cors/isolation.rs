@@ -0,0 +1,274 @@
+//! Cross-origin isolation headers, set alongside CORS from the same origin
+//! allowlist
+//!
+//! - `Cross-Origin-Resource-Policy` restricts which sites may embed a
+//!   resource in `no-cors` mode (images, scripts, ...)
+//!   https://fetch.spec.whatwg.org/#cross-origin-resource-policy-header
+//! - `Cross-Origin-Opener-Policy` isolates a document from cross-origin
+//!   windows which open it or which it opens
+//!   https://html.spec.whatwg.org/multipage/browsers.html#cross-origin-opener-policies
+//! - `Cross-Origin-Embedder-Policy` requires every resource a document
+//!   embeds to opt in through CORP or CORS
+//!   https://html.spec.whatwg.org/multipage/browsers.html#coep
+//!
+//! A document which sets COOP `same-origin` and COEP `require-corp` is
+//! cross-origin isolated, which unlocks e.g. `SharedArrayBuffer`.
+
+use super::builder::CorsBuilder;
+use super::config::AllowedOrigins;
+#[cfg(feature = "tower")]
+use super::isolation_service::IsolationLayer;
+
+use http::{
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    Request,
+};
+#[cfg(feature = "tower")]
+use std::sync::Arc;
+
+pub const CROSS_ORIGIN_RESOURCE_POLICY: HeaderName =
+    HeaderName::from_static("cross-origin-resource-policy");
+pub const CROSS_ORIGIN_OPENER_POLICY: HeaderName =
+    HeaderName::from_static("cross-origin-opener-policy");
+pub const CROSS_ORIGIN_OPENER_POLICY_REPORT_ONLY: HeaderName =
+    HeaderName::from_static("cross-origin-opener-policy-report-only");
+pub const CROSS_ORIGIN_EMBEDDER_POLICY: HeaderName =
+    HeaderName::from_static("cross-origin-embedder-policy");
+pub const CROSS_ORIGIN_EMBEDDER_POLICY_REPORT_ONLY: HeaderName =
+    HeaderName::from_static("cross-origin-embedder-policy-report-only");
+
+/// The value of `Cross-Origin-Resource-Policy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourcePolicy {
+    SameOrigin,
+    SameSite,
+    CrossOrigin,
+}
+
+impl ResourcePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResourcePolicy::SameOrigin => "same-origin",
+            ResourcePolicy::SameSite => "same-site",
+            ResourcePolicy::CrossOrigin => "cross-origin",
+        }
+    }
+}
+
+/// The value of `Cross-Origin-Opener-Policy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenerPolicy {
+    UnsafeNone,
+    SameOriginAllowPopups,
+    SameOrigin,
+    NoopenerAllowPopups,
+}
+
+impl OpenerPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OpenerPolicy::UnsafeNone => "unsafe-none",
+            OpenerPolicy::SameOriginAllowPopups => "same-origin-allow-popups",
+            OpenerPolicy::SameOrigin => "same-origin",
+            OpenerPolicy::NoopenerAllowPopups => "noopener-allow-popups",
+        }
+    }
+}
+
+/// The value of `Cross-Origin-Embedder-Policy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbedderPolicy {
+    UnsafeNone,
+    RequireCorp,
+    Credentialless,
+}
+
+impl EmbedderPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmbedderPolicy::UnsafeNone => "unsafe-none",
+            EmbedderPolicy::RequireCorp => "require-corp",
+            EmbedderPolicy::Credentialless => "credentialless",
+        }
+    }
+}
+
+/// Build the configuration of an `IsolationLayer`
+#[derive(Debug, Default, Clone)]
+pub struct IsolationBuilder {
+    allowed_origins: AllowedOrigins,
+    resource_policy: Option<ResourcePolicy>,
+    opener_policy: Option<OpenerPolicy>,
+    opener_policy_report_only: Option<OpenerPolicy>,
+    embedder_policy: Option<EmbedderPolicy>,
+    embedder_policy_report_only: Option<EmbedderPolicy>,
+    report_to: Option<String>,
+}
+
+impl IsolationBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The preset of a cross-origin isolated document: COOP `same-origin`,
+    /// COEP `require-corp` and CORP `same-origin`, relaxed for the allowed
+    /// origins
+    pub fn cross_origin_isolated() -> Self {
+        IsolationBuilder::new()
+            .resource_policy(ResourcePolicy::SameOrigin)
+            .opener_policy(OpenerPolicy::SameOrigin)
+            .embedder_policy(EmbedderPolicy::RequireCorp)
+    }
+
+    /// The origins for which `Cross-Origin-Resource-Policy` is relaxed to
+    /// `cross-origin`, usually the allowlist of the CORS configuration, see
+    /// `CorsBuilder::isolation`
+    pub fn allow_origins(mut self, origins: AllowedOrigins) -> Self {
+        self.allowed_origins = origins;
+        self
+    }
+
+    /// Send `Cross-Origin-Resource-Policy`.
+    ///
+    /// Requests from an allowed origin receive `cross-origin` instead.
+    /// Browsers send no `Origin` header with `no-cors` `GET` requests, so
+    /// embedding through e.g. `<img>` is governed by this policy alone.
+    pub fn resource_policy(mut self, policy: ResourcePolicy) -> Self {
+        self.resource_policy = Some(policy);
+        self
+    }
+
+    pub fn opener_policy(mut self, policy: OpenerPolicy) -> Self {
+        self.opener_policy = Some(policy);
+        self
+    }
+
+    /// Send `Cross-Origin-Opener-Policy-Report-Only`, which reports the
+    /// violations of the policy without enforcing it
+    pub fn opener_policy_report_only(mut self, policy: OpenerPolicy) -> Self {
+        self.opener_policy_report_only = Some(policy);
+        self
+    }
+
+    pub fn embedder_policy(mut self, policy: EmbedderPolicy) -> Self {
+        self.embedder_policy = Some(policy);
+        self
+    }
+
+    /// Send `Cross-Origin-Embedder-Policy-Report-Only`, which reports the
+    /// violations of the policy without enforcing it
+    pub fn embedder_policy_report_only(mut self, policy: EmbedderPolicy) -> Self {
+        self.embedder_policy_report_only = Some(policy);
+        self
+    }
+
+    /// The Reporting API endpoint, declared in `Reporting-Endpoints`, to
+    /// which COOP and COEP violations are reported
+    pub fn report_to(mut self, endpoint: impl Into<String>) -> Self {
+        self.report_to = Some(endpoint.into());
+        self
+    }
+
+    pub fn into_config(self) -> IsolationConfig {
+        let Self {
+            allowed_origins,
+            resource_policy,
+            opener_policy,
+            opener_policy_report_only,
+            embedder_policy,
+            embedder_policy_report_only,
+            report_to,
+        } = self;
+
+        let value = |policy: &str| {
+            let value = match report_to {
+                Some(ref endpoint) => format!("{}; report-to=\"{}\"", policy, endpoint),
+                None => policy.to_string(),
+            };
+            HeaderValue::from_str(&value).expect("Invalid report-to endpoint")
+        };
+
+        let mut headers = HeaderMap::new();
+        let policies = [
+            (
+                CROSS_ORIGIN_OPENER_POLICY,
+                opener_policy.map(|p| p.as_str()),
+            ),
+            (
+                CROSS_ORIGIN_OPENER_POLICY_REPORT_ONLY,
+                opener_policy_report_only.map(|p| p.as_str()),
+            ),
+            (
+                CROSS_ORIGIN_EMBEDDER_POLICY,
+                embedder_policy.map(|p| p.as_str()),
+            ),
+            (
+                CROSS_ORIGIN_EMBEDDER_POLICY_REPORT_ONLY,
+                embedder_policy_report_only.map(|p| p.as_str()),
+            ),
+        ];
+        for (name, policy) in policies {
+            if let Some(policy) = policy {
+                headers.insert(name, value(policy));
+            }
+        }
+
+        IsolationConfig {
+            allowed_origins: allowed_origins.normalized_or_skip(),
+            resource_policy,
+            headers,
+        }
+    }
+
+    #[cfg(feature = "tower")]
+    pub fn into_layer(self) -> IsolationLayer {
+        IsolationLayer::new(Arc::new(self.into_config()))
+    }
+}
+
+impl CorsBuilder {
+    /// An `IsolationBuilder` which shares the origin allowlist of this
+    /// configuration
+    pub fn isolation(&self) -> IsolationBuilder {
+        IsolationBuilder::new().allow_origins(self.effective_allowed_origins())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IsolationConfig {
+    pub allowed_origins: AllowedOrigins,
+    pub resource_policy: Option<ResourcePolicy>,
+    /// The COOP and COEP headers, which do not depend on the request
+    pub headers: HeaderMap,
+}
+
+impl IsolationConfig {
+    /// The isolation headers of the response to `request`
+    pub fn response_headers<B>(&self, request: &Request<B>) -> HeaderMap {
+        let mut headers = self.headers.clone();
+
+        let policy = match self.resource_policy {
+            Some(ResourcePolicy::CrossOrigin) => Some(ResourcePolicy::CrossOrigin),
+            Some(policy) => {
+                headers.insert(header::VARY, HeaderValue::from_static("origin"));
+                let allowed = request.headers().get(header::ORIGIN).is_some_and(|origin| {
+                    self.allowed_origins.origin_allowed(origin).unwrap_or(false)
+                });
+                Some(if allowed {
+                    ResourcePolicy::CrossOrigin
+                } else {
+                    policy
+                })
+            }
+            None => None,
+        };
+        if let Some(policy) = policy {
+            headers.insert(
+                CROSS_ORIGIN_RESOURCE_POLICY,
+                HeaderValue::from_static(policy.as_str()),
+            );
+        }
+
+        headers
+    }
+}
@@ -0,0 +1,100 @@
+use super::isolation::IsolationConfig;
+
+use futures_util::ready;
+use http::{HeaderMap, Request, Response};
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+/// Sets the cross-origin isolation headers of an `IsolationConfig` on every
+/// response of the inner service.
+///
+/// Headers set by the inner service take precedence, so a route can
+/// override the policy of a layer applied to the whole router.
+#[derive(Debug, Clone)]
+pub struct IsolationLayer {
+    config: Arc<IsolationConfig>,
+}
+
+impl IsolationLayer {
+    pub fn new(config: Arc<IsolationConfig>) -> Self {
+        Self { config }
+    }
+}
+
+impl<S> Layer<S> for IsolationLayer {
+    type Service = IsolationService<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        IsolationService::new(inner, self.config.clone())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IsolationService<S> {
+    inner: S,
+    config: Arc<IsolationConfig>,
+}
+
+impl<S> IsolationService<S> {
+    pub fn new(inner: S, config: Arc<IsolationConfig>) -> IsolationService<S> {
+        IsolationService { inner, config }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for IsolationService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = IsolationFuture<S::Future>;
+
+    fn poll_ready(&mut self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(ctx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let headers = self.config.response_headers(&request);
+        IsolationFuture {
+            future: self.inner.call(request),
+            headers: Some(headers),
+        }
+    }
+}
+
+pin_project! {
+    pub struct IsolationFuture<F> {
+        #[pin] future: F,
+        headers: Option<HeaderMap>,
+    }
+}
+
+impl<F, ResBody, E> Future for IsolationFuture<F>
+where
+    F: Future<Output = Result<Response<ResBody>, E>>,
+{
+    type Output = Result<Response<ResBody>, E>;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut response = ready!(this.future.poll(ctx))?;
+
+        let headers = this.headers.take().expect("poll called twice");
+        let response_headers = response.headers_mut();
+        for (name, value) in headers {
+            let name = name.expect("isolation headers have a single value");
+            if name == http::header::VARY {
+                response_headers.append(name, value);
+            } else {
+                response_headers.entry(name).or_insert(value);
+            }
+        }
+
+        Poll::Ready(Ok(response))
+    }
+}
@@ -4,6 +4,7 @@ use std::sync::Arc;
 
 use tower::Layer;
 
+#[derive(Debug, Clone)]
 pub struct CorsLayer {
     config: Arc<Config>,
 }
@@ -0,0 +1,140 @@
+//! A set of request methods, optimized for the standard methods
+//!
+//! The standard methods are stored as a bitset, so that checking the
+//! requested method of a preflight is a single mask test. Extension methods
+//! are kept in a small sorted list.
+
+use http::{HeaderValue, Method};
+use std::{fmt, iter::FromIterator};
+
+/// The standard methods in canonical order, the order of RFC 9110 followed
+/// by `PATCH`
+static STANDARD: [Method; 9] = [
+    Method::GET,
+    Method::HEAD,
+    Method::POST,
+    Method::PUT,
+    Method::DELETE,
+    Method::CONNECT,
+    Method::OPTIONS,
+    Method::TRACE,
+    Method::PATCH,
+];
+
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct MethodSet {
+    standard: u16,
+    extensions: Vec<Method>,
+}
+
+impl MethodSet {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add a method, returning whether it was not yet present
+    pub fn insert(&mut self, method: Method) -> bool {
+        match standard_bit(&method) {
+            Some(bit) => {
+                let inserted = self.standard & bit == 0;
+                self.standard |= bit;
+                inserted
+            }
+            None => match self
+                .extensions
+                .binary_search_by(|m| m.as_str().cmp(method.as_str()))
+            {
+                Ok(_) => false,
+                Err(idx) => {
+                    self.extensions.insert(idx, method);
+                    true
+                }
+            },
+        }
+    }
+
+    pub fn contains(&self, method: &Method) -> bool {
+        match standard_bit(method) {
+            Some(bit) => self.standard & bit != 0,
+            None => self
+                .extensions
+                .binary_search_by(|m| m.as_str().cmp(method.as_str()))
+                .is_ok(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.standard.count_ones() as usize + self.extensions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.standard == 0 && self.extensions.is_empty()
+    }
+
+    /// The methods in canonical order: the standard methods in the order of
+    /// `STANDARD`, then the extension methods in byte order
+    pub fn iter(&self) -> impl Iterator<Item = &Method> {
+        STANDARD
+            .iter()
+            .enumerate()
+            .filter(move |(i, _)| self.standard & (1 << i) != 0)
+            .map(|(_, method)| method)
+            .chain(self.extensions.iter())
+    }
+
+    /// The comma separated methods in canonical order, as sent in
+    /// `Access-Control-Allow-Methods`
+    pub fn to_header_value(&self) -> HeaderValue {
+        let value = self
+            .iter()
+            .map(Method::as_str)
+            .collect::<Vec<_>>()
+            .join(",");
+        HeaderValue::from_str(&value).expect("methods are valid header values")
+    }
+}
+
+/// The bit of a standard method, its index in `STANDARD`
+fn standard_bit(method: &Method) -> Option<u16> {
+    let index = match *method {
+        Method::GET => 0,
+        Method::HEAD => 1,
+        Method::POST => 2,
+        Method::PUT => 3,
+        Method::DELETE => 4,
+        Method::CONNECT => 5,
+        Method::OPTIONS => 6,
+        Method::TRACE => 7,
+        Method::PATCH => 8,
+        _ => return None,
+    };
+    Some(1 << index)
+}
+
+impl fmt::Debug for MethodSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl Extend<Method> for MethodSet {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = Method>,
+    {
+        for method in iter {
+            self.insert(method);
+        }
+    }
+}
+
+impl FromIterator<Method> for MethodSet {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = Method>,
+    {
+        let mut set = MethodSet::new();
+        set.extend(iter);
+        set
+    }
+}
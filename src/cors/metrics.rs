@@ -0,0 +1,240 @@
+//! Prometheus-style counters for requests evaluated by
+//! `Config::process_request`, and therefore by `CorsService`, enabled by
+//! the `metrics` feature.
+//!
+//! The following counters are recorded:
+//!
+//! - `cors_requests_total{kind}`: every request, where `kind` is one of
+//!   `simple`, `preflight` or `no_origin`. Browsers only send a preflight
+//!   when their preflight cache misses, so the `preflight` series is the
+//!   cache-miss volume.
+//! - `cors_denied_total{kind, reason, origin}`: every rejected request,
+//!   where `reason` is `InvalidRequest::reason`. The `origin` label is
+//!   controlled by `OriginLabel` to bound its cardinality.
+
+use super::config::{CorsResource, InvalidRequest, RequestKind};
+
+use http::{header, HeaderValue, Request};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    fmt::Write,
+    sync::{Arc, Mutex},
+};
+
+pub const REQUESTS_TOTAL: &str = "cors_requests_total";
+pub const DENIED_TOTAL: &str = "cors_denied_total";
+
+/// Value of the `origin` label when the real origin is not reported
+pub const OTHER_ORIGIN: &str = "other";
+
+/// A sink for counters, e.g. an adapter to a Prometheus registry
+pub trait MetricsRecorder: Send + Sync {
+    fn increment_counter(&self, name: &'static str, labels: &[(&'static str, &str)]);
+}
+
+/// Controls which origins are reported verbatim in the `origin` label.
+/// Every other origin is reported as `"other"`.
+#[derive(Debug, Clone, Default)]
+pub enum OriginLabel {
+    /// Never report the origin
+    #[default]
+    Omit,
+
+    /// Only report origins in this set
+    Allowlisted(BTreeSet<HeaderValue>),
+
+    /// Report the `n` origins denied most frequently so far. The counts
+    /// are estimated over the `4 * n` most frequent origins, so that
+    /// memory stays bounded: an origin which becomes frequent replaces one
+    /// which is not, and the reported origins change with the traffic.
+    /// At most `n` origins are reported at any time, but over time the
+    /// label takes more than `n` values.
+    TopN(usize),
+}
+
+/// Records counters for the decisions taken by a `Config`
+#[derive(Clone)]
+pub struct CorsMetrics {
+    recorder: Arc<dyn MetricsRecorder>,
+    origin_label: OriginLabel,
+    origin_counts: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl CorsMetrics {
+    pub fn new<R>(recorder: R) -> Self
+    where
+        R: MetricsRecorder + 'static,
+    {
+        Self::from_arc(Arc::new(recorder))
+    }
+
+    pub fn from_arc(recorder: Arc<dyn MetricsRecorder>) -> Self {
+        CorsMetrics {
+            recorder,
+            origin_label: OriginLabel::default(),
+            origin_counts: Default::default(),
+        }
+    }
+
+    /// Set how the `origin` label of `cors_denied_total` is populated
+    pub fn origin_label(mut self, origin_label: OriginLabel) -> Self {
+        self.origin_label = origin_label;
+        self
+    }
+
+    pub fn record<B>(&self, request: &Request<B>, result: &Result<CorsResource, InvalidRequest>) {
+        let kind = RequestKind::of(request).as_str();
+        self.recorder
+            .increment_counter(REQUESTS_TOTAL, &[("kind", kind)]);
+
+        if let Err(e) = result {
+            let origin = request.headers().get(header::ORIGIN);
+            let origin = self.origin_label_value(origin);
+            self.recorder.increment_counter(
+                DENIED_TOTAL,
+                &[("kind", kind), ("reason", e.reason()), ("origin", &origin)],
+            );
+        }
+    }
+
+    fn origin_label_value(&self, origin: Option<&HeaderValue>) -> String {
+        let origin = match origin {
+            Some(origin) => origin,
+            None => return OTHER_ORIGIN.to_string(),
+        };
+
+        let reported = match self.origin_label {
+            OriginLabel::Omit => false,
+            #[allow(clippy::mutable_key_type)]
+            OriginLabel::Allowlisted(ref origins) => origins.contains(origin),
+            OriginLabel::TopN(n) => match origin.to_str() {
+                Ok(origin) => self.count_origin(origin, n),
+                Err(_) => false,
+            },
+        };
+
+        match origin.to_str() {
+            Ok(origin) if reported => origin.to_string(),
+            _ => OTHER_ORIGIN.to_string(),
+        }
+    }
+
+    /// Count an occurrence of `origin` and whether it is now among the `n`
+    /// most frequent origins, with the space-saving algorithm: when all
+    /// slots are taken, the least frequent origin is replaced and its
+    /// count is inherited as an upper bound.
+    /// https://doi.org/10.1007/978-3-540-30570-5_27
+    fn count_origin(&self, origin: &str, n: usize) -> bool {
+        let mut counts = self.origin_counts.lock().expect("metrics lock poisoned");
+        if let Some(count) = counts.get_mut(origin) {
+            *count += 1;
+        } else if counts.len() < n.saturating_mul(4) {
+            counts.insert(origin.to_string(), 1);
+        } else {
+            let least = counts
+                .iter()
+                .min_by_key(|(_, count)| **count)
+                .map(|(origin, count)| (origin.clone(), *count));
+            match least {
+                Some((least, count)) => {
+                    counts.remove(&least);
+                    counts.insert(origin.to_string(), count + 1);
+                }
+                // `n` is 0
+                None => return false,
+            }
+        }
+
+        // Ties are won by the origins already reported
+        let count = counts[origin];
+        let higher = counts
+            .iter()
+            .filter(|(other, other_count)| other.as_str() != origin && **other_count >= count)
+            .count();
+        higher < n
+    }
+}
+
+impl fmt::Debug for CorsMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CorsMetrics")
+            .field("origin_label", &self.origin_label)
+            .finish()
+    }
+}
+
+type Labels = Vec<(&'static str, String)>;
+
+/// A `MetricsRecorder` which keeps counters in memory, for tests and for
+/// serving a scrape endpoint directly
+#[derive(Debug, Default)]
+pub struct InMemoryRecorder {
+    counters: Mutex<BTreeMap<(&'static str, Labels), u64>>,
+}
+
+impl InMemoryRecorder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The current value of the counter with exactly these labels
+    pub fn counter(&self, name: &str, labels: &[(&str, &str)]) -> u64 {
+        let counters = self.counters.lock().expect("metrics lock poisoned");
+        counters
+            .iter()
+            .find(|((n, l), _)| {
+                *n == name
+                    && l.len() == labels.len()
+                    && l.iter().zip(labels).all(|(a, b)| a.0 == b.0 && a.1 == b.1)
+            })
+            .map(|(_, v)| *v)
+            .unwrap_or(0)
+    }
+
+    /// The sum of all series of the counter
+    pub fn total(&self, name: &str) -> u64 {
+        let counters = self.counters.lock().expect("metrics lock poisoned");
+        counters
+            .iter()
+            .filter(|((n, _), _)| *n == name)
+            .map(|(_, v)| *v)
+            .sum()
+    }
+
+    /// Render all counters in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let counters = self.counters.lock().expect("metrics lock poisoned");
+        let mut out = String::new();
+        let mut last_name = None;
+        for ((name, labels), value) in counters.iter() {
+            if last_name != Some(*name) {
+                let _ = writeln!(out, "# TYPE {} counter", name);
+                last_name = Some(*name);
+            }
+            let labels: Vec<_> = labels
+                .iter()
+                .map(|(k, v)| format!("{}=\"{}\"", k, v.replace('\\', "\\\\").replace('"', "\\\"")))
+                .collect();
+            let _ = writeln!(out, "{}{{{}}} {}", name, labels.join(","), value);
+        }
+        out
+    }
+}
+
+impl MetricsRecorder for InMemoryRecorder {
+    fn increment_counter(&self, name: &'static str, labels: &[(&'static str, &str)]) {
+        let labels = labels.iter().map(|(k, v)| (*k, v.to_string())).collect();
+        let mut counters = self.counters.lock().expect("metrics lock poisoned");
+        *counters.entry((name, labels)).or_insert(0) += 1;
+    }
+}
+
+impl<R> MetricsRecorder for Arc<R>
+where
+    R: MetricsRecorder + ?Sized,
+{
+    fn increment_counter(&self, name: &'static str, labels: &[(&'static str, &str)]) {
+        (**self).increment_counter(name, labels)
+    }
+}
@@ -1,9 +1,57 @@
+#[cfg(feature = "tower")]
+pub mod body;
 pub mod builder;
 pub mod config;
+pub mod csrf;
+pub mod fetch_metadata;
+pub mod index;
+#[cfg(feature = "tower-http")]
+pub mod interop;
+pub mod isolation;
+#[cfg(feature = "tower")]
+pub mod isolation_service;
+#[cfg(feature = "tower")]
 pub mod layer;
+pub mod methods;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod origin;
+pub mod pattern;
+pub mod report;
+pub mod routes;
+pub mod same_origin;
+#[cfg(feature = "tower")]
 pub mod service;
+#[cfg(all(feature = "tower", any(test, feature = "test-util")))]
+pub mod simulator;
+#[cfg(feature = "tracing")]
+mod trace;
+pub mod websocket;
 
+#[cfg(feature = "tower")]
+pub use body::*;
 pub use builder::*;
 pub use config::*;
+pub use csrf::*;
+pub use fetch_metadata::*;
+pub use index::*;
+#[cfg(feature = "tower-http")]
+pub use interop::*;
+pub use isolation::*;
+#[cfg(feature = "tower")]
+pub use isolation_service::*;
+#[cfg(feature = "tower")]
 pub use layer::*;
+pub use methods::*;
+#[cfg(feature = "metrics")]
+pub use metrics::*;
+pub use origin::*;
+pub use pattern::*;
+pub use report::*;
+pub use routes::*;
+pub use same_origin::*;
+#[cfg(feature = "tower")]
 pub use service::*;
+#[cfg(all(feature = "tower", any(test, feature = "test-util")))]
+pub use simulator::*;
+pub use websocket::*;
@@ -0,0 +1,204 @@
+//! Parsing and normalization of serialized origins
+//!
+//! https://html.spec.whatwg.org/multipage/origin.html#ascii-serialisation-of-an-origin
+
+use http::HeaderValue;
+use std::{error, fmt, net::Ipv6Addr, str::FromStr};
+
+/// A tuple origin (scheme, host, port) in its normalized form: scheme and
+/// host are lowercase, the host is punycode encoded, and the default port
+/// of the scheme is elided.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Origin {
+    scheme: String,
+    host: String,
+    port: Option<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidOrigin {
+    /// The value is not of the form `scheme://host[:port]`
+    Syntax,
+    InvalidScheme,
+    InvalidHost,
+    InvalidPort,
+    /// The value contains a path, query or fragment
+    UnexpectedComponent,
+    /// The value is not valid UTF-8
+    Encoding,
+}
+
+impl error::Error for InvalidOrigin {}
+
+impl fmt::Display for InvalidOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl Origin {
+    /// Parse and normalize a configured origin.
+    ///
+    /// A single trailing `/` is tolerated, any other path as well as a
+    /// query or fragment is rejected.
+    pub fn parse(s: &str) -> Result<Origin, InvalidOrigin> {
+        Origin::parse_serialized(s, true)
+    }
+
+    /// Parse and normalize the value of an `Origin` header. Browsers never
+    /// send a path, so unlike `parse` a trailing `/` is rejected. The
+    /// opaque origin `null` is not a tuple origin and is rejected.
+    pub fn from_header(value: &HeaderValue) -> Result<Origin, InvalidOrigin> {
+        let value = std::str::from_utf8(value.as_bytes()).map_err(|_| InvalidOrigin::Encoding)?;
+        Origin::parse_serialized(value, false)
+    }
+
+    fn parse_serialized(s: &str, allow_trailing_slash: bool) -> Result<Origin, InvalidOrigin> {
+        let (scheme, rest) = split_scheme(s)?;
+        let authority = strip_empty_path(rest, allow_trailing_slash)?;
+        let (host, port) = split_port(authority)?;
+
+        let host = normalize_host(host)?;
+        let port = match port {
+            Some(port) if Some(port) == default_port(&scheme) => None,
+            port => port,
+        };
+
+        Ok(Origin { scheme, host, port })
+    }
+
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    /// The lowercase, punycode encoded host. IPv6 addresses are enclosed in
+    /// brackets.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// The explicit port, `None` when it is the default port of the scheme
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+
+    /// The port, falling back to the default port of the scheme
+    pub fn port_or_default(&self) -> Option<u16> {
+        self.port.or_else(|| default_port(&self.scheme))
+    }
+
+    pub fn to_header_value(&self) -> HeaderValue {
+        HeaderValue::from_str(&self.to_string()).expect("normalized origin is a valid header")
+    }
+}
+
+impl FromStr for Origin {
+    type Err = InvalidOrigin;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Origin::parse(s)
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}", self.scheme, self.host)?;
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+        Ok(())
+    }
+}
+
+/// The origin of a URL, `None` if it is not a URL with a tuple origin
+pub(crate) fn url_origin(url: &str) -> Option<Origin> {
+    let authority_start = url.find("://")? + 3;
+    let authority_end = url[authority_start..]
+        .find(['/', '?', '#'])
+        .map_or(url.len(), |idx| authority_start + idx);
+    Origin::parse(&url[..authority_end]).ok()
+}
+
+pub fn default_port(scheme: &str) -> Option<u16> {
+    match scheme {
+        "http" | "ws" => Some(80),
+        "https" | "wss" => Some(443),
+        "ftp" => Some(21),
+        _ => None,
+    }
+}
+
+fn split_scheme(s: &str) -> Result<(String, &str), InvalidOrigin> {
+    let idx = s.find("://").ok_or(InvalidOrigin::Syntax)?;
+    let (scheme, rest) = (&s[..idx], &s[idx + 3..]);
+
+    let mut chars = scheme.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.');
+    if !valid {
+        return Err(InvalidOrigin::InvalidScheme);
+    }
+
+    Ok((scheme.to_ascii_lowercase(), rest))
+}
+
+fn strip_empty_path(rest: &str, allow_trailing_slash: bool) -> Result<&str, InvalidOrigin> {
+    if rest.contains(['?', '#']) {
+        return Err(InvalidOrigin::UnexpectedComponent);
+    }
+    match rest.find('/') {
+        Some(idx) if allow_trailing_slash && idx + 1 == rest.len() => Ok(&rest[..idx]),
+        Some(_) => Err(InvalidOrigin::UnexpectedComponent),
+        None => Ok(rest),
+    }
+}
+
+fn split_port(authority: &str) -> Result<(&str, Option<u16>), InvalidOrigin> {
+    if authority.contains('@') {
+        return Err(InvalidOrigin::UnexpectedComponent);
+    }
+
+    let port_idx = if authority.starts_with('[') {
+        let end = authority.find(']').ok_or(InvalidOrigin::InvalidHost)?;
+        match &authority[end + 1..] {
+            "" => None,
+            rest if rest.starts_with(':') => Some(end + 1),
+            _ => return Err(InvalidOrigin::InvalidHost),
+        }
+    } else {
+        authority.rfind(':')
+    };
+
+    match port_idx {
+        Some(idx) => Ok((&authority[..idx], Some(parse_port(&authority[idx + 1..])?))),
+        None => Ok((authority, None)),
+    }
+}
+
+pub(crate) fn parse_port(port: &str) -> Result<u16, InvalidOrigin> {
+    if port.is_empty() || !port.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(InvalidOrigin::InvalidPort);
+    }
+    port.parse().map_err(|_| InvalidOrigin::InvalidPort)
+}
+
+pub(crate) fn normalize_host(host: &str) -> Result<String, InvalidOrigin> {
+    if host.is_empty() {
+        return Err(InvalidOrigin::InvalidHost);
+    }
+
+    if host.starts_with('[') && host.ends_with(']') {
+        let addr: Ipv6Addr = host[1..host.len() - 1]
+            .parse()
+            .map_err(|_| InvalidOrigin::InvalidHost)?;
+        return Ok(format!("[{}]", addr));
+    }
+
+    let host = idna::domain_to_ascii(host).map_err(|_| InvalidOrigin::InvalidHost)?;
+    let forbidden = |c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == '_');
+    if host.is_empty() || host.contains(forbidden) {
+        return Err(InvalidOrigin::InvalidHost);
+    }
+
+    Ok(host)
+}
@@ -0,0 +1,204 @@
+//! Structured origin rules over scheme, host and port
+//!
+//! A pattern is written like an origin, where each component may be
+//! widened:
+//!
+//! - scheme: `*` matches any scheme
+//! - host: `*` matches any host, `*.corp.example` matches every subdomain
+//!   of `corp.example` (but not `corp.example` itself)
+//! - port: `*` matches any port, `8000-8999` matches an inclusive range.
+//!   Without a port only the default port of the scheme matches.
+//!
+//! Browsers omit the default port when serializing an origin, so
+//! `https://a.example` and `https://a.example:443` are the same origin.
+//! A range is therefore compared against the effective port: a range
+//! containing 443 matches `https://a.example`, one that does not (such as
+//! `8000-8999`) rejects it. An origin whose scheme has no default port and
+//! no explicit port never matches a range.
+//!
+//! e.g. `https://*.corp.example:8000-8999` or `http://localhost:*`
+
+use super::origin::{default_port, normalize_host, parse_port, InvalidOrigin, Origin};
+
+use std::{fmt, net::Ipv4Addr, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemePattern {
+    Any,
+    Exact(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostPattern {
+    Any,
+    Exact(String),
+    /// Every strict subdomain of the given host
+    Subdomains(String),
+    /// `localhost`, `127.0.0.0/8` and `[::1]`
+    Loopback,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortPattern {
+    /// Only the default port of the scheme
+    Default,
+    Any,
+    /// An inclusive range of ports, compared against the explicit port of
+    /// the origin or else the default port of its scheme
+    Range(u16, u16),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginPattern {
+    pub scheme: SchemePattern,
+    pub host: HostPattern,
+    pub port: PortPattern,
+}
+
+impl OriginPattern {
+    /// The given scheme on a loopback host on any port
+    pub fn loopback(scheme: &str) -> OriginPattern {
+        OriginPattern {
+            scheme: SchemePattern::Exact(scheme.to_ascii_lowercase()),
+            host: HostPattern::Loopback,
+            port: PortPattern::Any,
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<OriginPattern, InvalidOrigin> {
+        let idx = s.find("://").ok_or(InvalidOrigin::Syntax)?;
+        let (scheme, authority) = (&s[..idx], &s[idx + 3..]);
+        let authority = authority.strip_suffix('/').unwrap_or(authority);
+        if authority.contains(['/', '?', '#', '@']) {
+            return Err(InvalidOrigin::UnexpectedComponent);
+        }
+
+        let scheme = match scheme {
+            "*" => SchemePattern::Any,
+            scheme => {
+                // Validate and normalize the scheme through a placeholder origin
+                let origin = Origin::parse(&format!("{}://placeholder", scheme))?;
+                SchemePattern::Exact(origin.scheme().to_string())
+            }
+        };
+
+        let (host, port) = split_port_pattern(authority)?;
+
+        let host = match host {
+            "*" => HostPattern::Any,
+            host => match host.strip_prefix("*.") {
+                Some(suffix) => HostPattern::Subdomains(normalize_host(suffix)?),
+                None => HostPattern::Exact(normalize_host(host)?),
+            },
+        };
+
+        let port = match port {
+            None => PortPattern::Default,
+            Some("*") => PortPattern::Any,
+            Some(port) => match port.find('-') {
+                Some(idx) => {
+                    let (from, to) = (parse_port(&port[..idx])?, parse_port(&port[idx + 1..])?);
+                    if from > to {
+                        return Err(InvalidOrigin::InvalidPort);
+                    }
+                    PortPattern::Range(from, to)
+                }
+                None => {
+                    let port = parse_port(port)?;
+                    match scheme {
+                        SchemePattern::Exact(ref scheme) if default_port(scheme) == Some(port) => {
+                            PortPattern::Default
+                        }
+                        _ => PortPattern::Range(port, port),
+                    }
+                }
+            },
+        };
+
+        Ok(OriginPattern { scheme, host, port })
+    }
+
+    pub fn matches(&self, origin: &Origin) -> bool {
+        let scheme_matches = match self.scheme {
+            SchemePattern::Any => true,
+            SchemePattern::Exact(ref scheme) => scheme == origin.scheme(),
+        };
+
+        let host_matches = match self.host {
+            HostPattern::Any => true,
+            HostPattern::Exact(ref host) => host == origin.host(),
+            HostPattern::Subdomains(ref suffix) => is_subdomain(origin.host(), suffix),
+            HostPattern::Loopback => is_loopback(origin.host()),
+        };
+
+        let port_matches = match self.port {
+            PortPattern::Any => true,
+            PortPattern::Default => origin.port().is_none(),
+            PortPattern::Range(from, to) => origin
+                .port_or_default()
+                .is_some_and(|port| from <= port && port <= to),
+        };
+
+        scheme_matches && host_matches && port_matches
+    }
+}
+
+impl FromStr for OriginPattern {
+    type Err = InvalidOrigin;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OriginPattern::parse(s)
+    }
+}
+
+impl fmt::Display for OriginPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.scheme {
+            SchemePattern::Any => write!(f, "*://")?,
+            SchemePattern::Exact(ref scheme) => write!(f, "{}://", scheme)?,
+        }
+        match self.host {
+            HostPattern::Any => write!(f, "*")?,
+            HostPattern::Exact(ref host) => write!(f, "{}", host)?,
+            HostPattern::Subdomains(ref suffix) => write!(f, "*.{}", suffix)?,
+            HostPattern::Loopback => write!(f, "<loopback>")?,
+        }
+        match self.port {
+            PortPattern::Default => Ok(()),
+            PortPattern::Any => write!(f, ":*"),
+            PortPattern::Range(from, to) if from == to => write!(f, ":{}", from),
+            PortPattern::Range(from, to) => write!(f, ":{}-{}", from, to),
+        }
+    }
+}
+
+fn split_port_pattern(authority: &str) -> Result<(&str, Option<&str>), InvalidOrigin> {
+    if authority.starts_with('[') {
+        let end = authority.find(']').ok_or(InvalidOrigin::InvalidHost)?;
+        return match &authority[end + 1..] {
+            "" => Ok((authority, None)),
+            rest if rest.starts_with(':') => Ok((&authority[..=end], Some(&rest[1..]))),
+            _ => Err(InvalidOrigin::InvalidHost),
+        };
+    }
+
+    match authority.rfind(':') {
+        Some(idx) => Ok((&authority[..idx], Some(&authority[idx + 1..]))),
+        None => Ok((authority, None)),
+    }
+}
+
+fn is_loopback(host: &str) -> bool {
+    if host == "localhost" || host == "[::1]" {
+        return true;
+    }
+    host.parse::<Ipv4Addr>()
+        .map(|addr| addr.is_loopback())
+        .unwrap_or(false)
+}
+
+fn is_subdomain(host: &str, suffix: &str) -> bool {
+    host.len() > suffix.len() + 1
+        && host.ends_with(suffix)
+        && host.as_bytes()[host.len() - suffix.len() - 1] == b'.'
+}
@@ -0,0 +1,103 @@
+//! Report-only evaluation of a candidate configuration
+//!
+//! Like CSP's `Content-Security-Policy-Report-Only`, a stricter policy can
+//! be observed before it is enforced: `Config::process_request` evaluates
+//! the candidate for every request, always applies the decision of the
+//! enforced configuration, and reports each request on which both differ.
+
+use super::config::{Config, CorsResource, InvalidRequest, RequestKind};
+
+use http::{header, HeaderValue, Method, Request, Uri};
+use std::{fmt, sync::Arc};
+
+/// A request for which the candidate configuration of a `ReportOnly`
+/// would have taken a different decision than the enforced one
+#[derive(Debug)]
+pub struct Divergence<'a> {
+    pub method: &'a Method,
+    pub uri: &'a Uri,
+    pub origin: Option<&'a HeaderValue>,
+    pub kind: RequestKind,
+    /// The decision which was applied to the request
+    pub enforced: &'a Result<CorsResource, InvalidRequest>,
+    /// The decision the candidate configuration would have taken
+    pub candidate: &'a Result<CorsResource, InvalidRequest>,
+}
+
+impl Divergence<'_> {
+    /// Whether the candidate would deny a request the enforced
+    /// configuration allows, i.e. whether enforcing it would break a client
+    pub fn is_breaking(&self) -> bool {
+        self.enforced.is_ok() && self.candidate.is_err()
+    }
+}
+
+type Reporter = dyn Fn(&Divergence<'_>) + Send + Sync;
+
+/// Evaluates a candidate configuration alongside the enforced one without
+/// applying it, similar to CSP's report-only mode.
+///
+/// Every request whose outcome or response headers differ between both
+/// configurations is passed to the reporter.
+#[derive(Clone)]
+pub struct ReportOnly {
+    candidate: Arc<Config>,
+    reporter: Arc<Reporter>,
+}
+
+impl ReportOnly {
+    pub fn new<F>(candidate: Config, reporter: F) -> Self
+    where
+        F: Fn(&Divergence<'_>) + Send + Sync + 'static,
+    {
+        ReportOnly {
+            candidate: Arc::new(candidate),
+            reporter: Arc::new(reporter),
+        }
+    }
+
+    pub fn candidate(&self) -> &Config {
+        &self.candidate
+    }
+
+    /// Evaluate the candidate configuration for `request` and report a
+    /// divergence from the `enforced` decision, if any
+    pub fn check<B>(&self, request: &Request<B>, enforced: &Result<CorsResource, InvalidRequest>) {
+        // Evaluate without logging, a candidate denial is not a denial
+        let kind = RequestKind::of(request);
+        let candidate = self.candidate.evaluate(request, kind);
+        if same_decision(enforced, &candidate) {
+            return;
+        }
+
+        (self.reporter)(&Divergence {
+            method: request.method(),
+            uri: request.uri(),
+            origin: request.headers().get(header::ORIGIN),
+            kind,
+            enforced,
+            candidate: &candidate,
+        });
+    }
+}
+
+impl fmt::Debug for ReportOnly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReportOnly")
+            .field("candidate", &self.candidate)
+            .finish()
+    }
+}
+
+fn same_decision(
+    a: &Result<CorsResource, InvalidRequest>,
+    b: &Result<CorsResource, InvalidRequest>,
+) -> bool {
+    match (a, b) {
+        (Ok(CorsResource::Preflight(a)), Ok(CorsResource::Preflight(b)))
+        | (Ok(CorsResource::Simple(a)), Ok(CorsResource::Simple(b)))
+        | (Ok(CorsResource::Options(a)), Ok(CorsResource::Options(b))) => a == b,
+        (Err(a), Err(b)) => a.reason() == b.reason(),
+        _ => false,
+    }
+}
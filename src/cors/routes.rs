@@ -0,0 +1,264 @@
+//! The methods supported by each route, to answer preflights and `OPTIONS`
+//! requests with the methods the target path actually supports
+//!
+//! Paths are templates in the syntax of axum: `/users/:id` captures one
+//! segment, `/files/*path` captures the rest of the path. As in axum, a
+//! literal segment takes precedence over a capture.
+//!
+//! The methods are best derived from the router with `RouteMethods::probe`,
+//! which reads them from the `405 Method Not Allowed` axum answers for a
+//! method no route handles, so that they cannot drift from the handlers.
+
+use super::methods::MethodSet;
+
+#[cfg(feature = "tower")]
+use http::{header, Request, Response, StatusCode};
+use http::{HeaderValue, Method};
+#[cfg(feature = "tower")]
+use std::{error, fmt};
+#[cfg(feature = "tower")]
+use tower::{Service, ServiceExt};
+
+#[derive(Debug, Clone, Default)]
+pub struct RouteMethods {
+    routes: Vec<Route>,
+}
+
+#[derive(Debug, Clone)]
+struct Route {
+    template: Vec<Segment>,
+    methods: MethodSet,
+    allow_methods_header: HeaderValue,
+    allow_header: HeaderValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Capture,
+    Wildcard,
+}
+
+/// The methods of the route matching a path
+#[derive(Debug, Clone, Copy)]
+pub struct RouteMatch<'a> {
+    route: &'a Route,
+}
+
+impl RouteMethods {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Derive the methods of each path from a service, e.g. an axum
+    /// `Router` before the CORS layer is added. Each path is requested once
+    /// with a method no route handles, and the methods are read from the
+    /// `Allow` header of the `405 Method Not Allowed` response. Captures in
+    /// a path are filled with a placeholder segment.
+    ///
+    /// No handler is called, unless a route has a fallback for unknown
+    /// methods.
+    ///
+    /// The request body type has to be named, e.g.
+    /// `RouteMethods::probe::<axum::body::Body, _, _>(router.clone(), paths)`.
+    #[cfg(feature = "tower")]
+    pub async fn probe<ReqBody, S, ResBody>(
+        service: S,
+        paths: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<RouteMethods, ProbeError<S::Error>>
+    where
+        S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone,
+        ReqBody: Default,
+    {
+        let mut routes = RouteMethods::new();
+        for path in paths {
+            let path = path.as_ref();
+            let request = Request::builder()
+                .method(Method::from_bytes(PROBE_METHOD).expect("valid probe method"))
+                .uri(probe_uri(path))
+                .body(ReqBody::default())
+                .map_err(|_| ProbeError::InvalidPath(path.to_string()))?;
+            let response = service
+                .clone()
+                .oneshot(request)
+                .await
+                .map_err(ProbeError::Service)?;
+
+            let allow = match response.headers().get(header::ALLOW) {
+                Some(allow) if response.status() == StatusCode::METHOD_NOT_ALLOWED => allow,
+                _ => {
+                    return Err(ProbeError::NoAllowHeader {
+                        path: path.to_string(),
+                        status: response.status(),
+                    })
+                }
+            };
+            let methods = allow
+                .as_bytes()
+                .split(|&b| b == b',')
+                .map(<[u8]>::trim_ascii)
+                .filter_map(|method| Method::from_bytes(method).ok())
+                .collect::<Vec<_>>();
+            routes = routes.route(path, methods);
+        }
+        Ok(routes)
+    }
+
+    /// Register the methods of a route, in addition to those registered
+    /// for the same path before. As in axum, a route handling `GET` also
+    /// handles `HEAD`.
+    pub fn route<I>(mut self, path: &str, methods: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Method>,
+    {
+        let template = parse_template(path);
+        let idx = match self.routes.iter().position(|r| r.template == template) {
+            Some(idx) => idx,
+            None => {
+                self.routes.push(Route {
+                    template,
+                    methods: MethodSet::new(),
+                    allow_methods_header: HeaderValue::from_static(""),
+                    allow_header: HeaderValue::from_static(""),
+                });
+                self.routes.len() - 1
+            }
+        };
+
+        let route = &mut self.routes[idx];
+        route.methods.extend(methods.into_iter().map(Into::into));
+        if route.methods.contains(&Method::GET) {
+            route.methods.insert(Method::HEAD);
+        }
+        route.allow_methods_header = route.methods.to_header_value();
+        let mut allow = route.methods.clone();
+        allow.insert(Method::OPTIONS);
+        route.allow_header = allow.to_header_value();
+        self
+    }
+
+    /// The route matching `path`, preferring literal segments over
+    /// captures and captures over wildcards from left to right
+    pub fn find(&self, path: &str) -> Option<RouteMatch<'_>> {
+        let segments = split_path(path).collect::<Vec<_>>();
+        self.routes
+            .iter()
+            .filter_map(|route| route.specificity(&segments).map(|s| (s, route)))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, route)| RouteMatch { route })
+    }
+}
+
+impl<'a> RouteMatch<'a> {
+    pub fn methods(&self) -> &'a MethodSet {
+        &self.route.methods
+    }
+
+    /// The value of `Access-Control-Allow-Methods` for the route
+    pub fn allow_methods_header(&self) -> &'a HeaderValue {
+        &self.route.allow_methods_header
+    }
+
+    /// The value of `Allow` for the route: its methods and `OPTIONS`
+    pub fn allow_header(&self) -> &'a HeaderValue {
+        &self.route.allow_header
+    }
+}
+
+impl Route {
+    /// The rank of each segment of the template if it matches the path,
+    /// higher is more specific
+    fn specificity(&self, path: &[&str]) -> Option<Vec<u8>> {
+        let mut specificity = Vec::with_capacity(self.template.len());
+        for (idx, segment) in self.template.iter().enumerate() {
+            match segment {
+                Segment::Wildcard => {
+                    // The wildcard captures at least one segment
+                    if path.len() <= idx {
+                        return None;
+                    }
+                    specificity.push(0);
+                    return Some(specificity);
+                }
+                Segment::Capture => {
+                    path.get(idx).filter(|s| !s.is_empty())?;
+                    specificity.push(1);
+                }
+                Segment::Literal(literal) => {
+                    if path.get(idx) != Some(&literal.as_str()) {
+                        return None;
+                    }
+                    specificity.push(2);
+                }
+            }
+        }
+        if path.len() == self.template.len() {
+            Some(specificity)
+        } else {
+            None
+        }
+    }
+}
+
+/// The method of the probe requests, which no route handles
+#[cfg(feature = "tower")]
+const PROBE_METHOD: &[u8] = b"X-CORS-PROBE";
+
+/// A path of a route template, with a placeholder for each capture
+#[cfg(feature = "tower")]
+fn probe_uri(path: &str) -> String {
+    let segments = split_path(path)
+        .map(|segment| {
+            if segment.starts_with(':') || segment.starts_with('*') {
+                "probe"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>();
+    format!("/{}", segments.join("/"))
+}
+
+/// Why the methods of a path could not be probed
+#[cfg(feature = "tower")]
+#[derive(Debug)]
+pub enum ProbeError<E> {
+    /// The path is not a valid URI path
+    InvalidPath(String),
+    /// The service failed
+    Service(E),
+    /// The service did not answer with `405 Method Not Allowed` and an
+    /// `Allow` header, e.g. because no route matches the path
+    NoAllowHeader { path: String, status: StatusCode },
+}
+
+#[cfg(feature = "tower")]
+impl<E: fmt::Debug> fmt::Display for ProbeError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+#[cfg(feature = "tower")]
+impl<E: fmt::Debug> error::Error for ProbeError<E> {}
+
+fn parse_template(path: &str) -> Vec<Segment> {
+    split_path(path)
+        .map(|segment| {
+            if segment.starts_with(':') {
+                Segment::Capture
+            } else if segment.starts_with('*') {
+                Segment::Wildcard
+            } else {
+                Segment::Literal(segment.to_string())
+            }
+        })
+        .collect()
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    let path = path.strip_prefix('/').unwrap_or(path);
+    // The root path has no segment
+    path.split('/').filter(move |_| !path.is_empty())
+}
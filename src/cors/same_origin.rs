@@ -0,0 +1,174 @@
+//! Detection of requests sent from the origin of the server itself
+//!
+//! The origin of the server is taken from the `Host` header, or the
+//! authority of the URI for HTTP/2. Behind a reverse proxy, which e.g.
+//! terminates TLS, the scheme and host the client used are only known from
+//! the headers the proxy adds. Those are client controlled unless the proxy
+//! sets them, so they are only read when trusted explicitly.
+
+use super::origin::{default_port, normalize_host, parse_port, Origin};
+
+use http::{
+    header::{self, HeaderName},
+    HeaderMap, Request,
+};
+
+pub const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+pub const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+
+/// Headers a trusted reverse proxy sets to describe the original request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForwardedHeaders {
+    /// `Forwarded` with the `proto` and `host` parameters, RFC 7239
+    Forwarded,
+    /// `X-Forwarded-Proto` and `X-Forwarded-Host`
+    XForwarded,
+}
+
+/// Determines the origin of the server to detect same-origin requests.
+///
+/// When several proxies append to the forwarded headers, the last entry is
+/// used: it was added by the proxy closest to this server, while earlier
+/// entries may have been sent by the client.
+#[derive(Debug, Clone, Default)]
+pub struct SameOrigin {
+    trusted: Vec<ForwardedHeaders>,
+    scheme: Option<String>,
+}
+
+/// The scheme, host and port the request was sent to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerOrigin {
+    pub scheme: String,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+/// Same-origin detection from the `Host` header alone
+pub(crate) static HOST_ONLY: SameOrigin = SameOrigin::new();
+
+impl SameOrigin {
+    pub const fn new() -> Self {
+        SameOrigin {
+            trusted: Vec::new(),
+            scheme: None,
+        }
+    }
+
+    /// Read the original scheme and host from the forwarded headers, which
+    /// the reverse proxy in front of this server sets
+    pub fn trust(mut self, headers: ForwardedHeaders) -> Self {
+        if !self.trusted.contains(&headers) {
+            self.trusted.push(headers);
+        }
+        self
+    }
+
+    /// The scheme of the server when no trusted header forwards it, e.g.
+    /// `https` behind a proxy which terminates TLS without forwarding the
+    /// scheme. Without it, the scheme is only known from the URI of HTTP/2
+    /// requests.
+    pub fn scheme(mut self, scheme: impl Into<String>) -> Self {
+        self.scheme = Some(scheme.into().to_ascii_lowercase());
+        self
+    }
+
+    /// The origin the request was sent to, or `None` if its scheme or host
+    /// cannot be determined
+    pub fn server_origin<B>(&self, request: &Request<B>) -> Option<ServerOrigin> {
+        let headers = request.headers();
+
+        // The scheme of the URI is known for HTTP/2 requests
+        let mut scheme = self
+            .scheme
+            .clone()
+            .or_else(|| request.uri().scheme_str().map(str::to_ascii_lowercase));
+        let mut authority = None;
+        for trusted in &self.trusted {
+            let (proto, host) = match trusted {
+                ForwardedHeaders::Forwarded => forwarded(headers),
+                ForwardedHeaders::XForwarded => (
+                    last_value(headers, &X_FORWARDED_PROTO),
+                    last_value(headers, &X_FORWARDED_HOST),
+                ),
+            };
+            scheme = proto.map(str::to_ascii_lowercase).or(scheme);
+            authority = host.map(str::to_string).or(authority);
+        }
+
+        let scheme = scheme?;
+        let authority = match authority {
+            Some(authority) => authority,
+            None => match headers.get(header::HOST) {
+                Some(host) => host.to_str().ok()?.to_string(),
+                None => request.uri().authority()?.as_str().to_string(),
+            },
+        };
+        let (host, port) = split_authority(&authority)?;
+
+        Some(ServerOrigin { scheme, host, port })
+    }
+
+    /// Whether the origin is the origin of the server, compared by scheme,
+    /// host and port. While the origin of the server is unknown, the request
+    /// is taken as cross-origin.
+    pub fn is_same_origin<B>(&self, request: &Request<B>, origin: &Origin) -> bool {
+        let server = match self.server_origin(request) {
+            Some(server) => server,
+            None => return false,
+        };
+        server.scheme == origin.scheme()
+            && server.host == origin.host()
+            && server.port.or_else(|| default_port(&server.scheme)) == origin.port_or_default()
+    }
+}
+
+/// The `proto` and `host` parameters of the last `Forwarded` element
+fn forwarded(headers: &HeaderMap) -> (Option<&str>, Option<&str>) {
+    let element = match last_value(headers, &header::FORWARDED) {
+        Some(element) => element,
+        None => return (None, None),
+    };
+
+    let (mut proto, mut host) = (None, None);
+    for pair in element.split(';') {
+        let (name, value) = match pair.find('=') {
+            Some(idx) => (pair[..idx].trim(), pair[idx + 1..].trim()),
+            None => continue,
+        };
+        let value = value.trim_matches('"');
+        if name.eq_ignore_ascii_case("proto") {
+            proto = Some(value);
+        } else if name.eq_ignore_ascii_case("host") {
+            host = Some(value);
+        }
+    }
+    (proto, host)
+}
+
+/// The last entry of a comma separated header, over all its lines
+fn last_value<'a>(headers: &'a HeaderMap, name: &HeaderName) -> Option<&'a str> {
+    let value = headers.get_all(name).iter().next_back()?.to_str().ok()?;
+    let value = value.rsplit(',').next()?.trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// The normalized host and the port of a `Host` header
+fn split_authority(authority: &str) -> Option<(String, Option<u16>)> {
+    let port_idx = match authority.rfind(':') {
+        Some(idx) if !authority[idx..].contains(']') => Some(idx),
+        _ => None,
+    };
+    let (host, port) = match port_idx {
+        Some(idx) => (
+            &authority[..idx],
+            Some(parse_port(&authority[idx + 1..]).ok()?),
+        ),
+        None => (authority, None),
+    };
+    Some((normalize_host(host).ok()?, port))
+}
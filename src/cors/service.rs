@@ -1,10 +1,8 @@
+use super::body::CorsBody;
 use super::config::*;
 
-use axum::body::{box_body, BoxBody};
-use bytes::Bytes;
 use futures_util::ready;
 use http::{self, HeaderMap, Request, Response, StatusCode};
-use log::debug;
 use pin_project_lite::pin_project;
 use std::{
     future::Future,
@@ -12,49 +10,79 @@ use std::{
     sync::Arc,
     task::{Context, Poll},
 };
-use tower::{BoxError, Service};
-
-#[derive(Debug, Clone)]
+use tower::Service;
+
+/// Applies a CORS `Config` to the requests of the inner service.
+///
+/// `poll_ready` passes the readiness of the inner service through, so its
+/// backpressure reaches the callers. Preflights and rejected requests are
+/// answered by `CorsService` itself and leave the readiness they were polled
+/// with unused: it is kept for the next request, and only consumed by a
+/// request which is forwarded.
+#[derive(Debug)]
 pub struct CorsService<S> {
     inner: S,
     config: Arc<Config>,
+    inner_ready: bool,
 }
 
 impl<S> CorsService<S> {
     pub fn new(inner: S, config: Arc<Config>) -> CorsService<S> {
-        CorsService { inner, config }
+        CorsService {
+            inner,
+            config,
+            inner_ready: false,
+        }
+    }
+}
+
+impl<S> Clone for CorsService<S>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        // Readiness belongs to this instance, a clone has to poll for its own
+        CorsService::new(self.inner.clone(), self.config.clone())
     }
 }
 
 impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for CorsService<S>
 where
-    ReqBody: Send + 'static,
-    ResBody: http_body::Body<Data = Bytes> + Send + Sync + 'static,
-    ResBody::Error: Into<BoxError>,
-    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone,
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
 {
-    type Response = Response<BoxBody>;
+    type Response = Response<CorsBody<ResBody>>;
     type Error = S::Error;
     type Future = CorsFuture<ReqBody, S>;
 
-    #[inline]
     fn poll_ready(&mut self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.inner.poll_ready(ctx)
+        if !self.inner_ready {
+            ready!(self.inner.poll_ready(ctx))?;
+            self.inner_ready = true;
+        }
+        Poll::Ready(Ok(()))
     }
 
     fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
-        let inner = match self.config.process_request(&request) {
-            Ok(CorsResource::Preflight(headers)) => CorsFutureInner::Handled {
-                headers: Some(headers),
-            },
-            Ok(CorsResource::Simple(headers)) => CorsFutureInner::Simple {
-                future: self.inner.call(request),
-                headers: Some(headers),
-            },
-            Err(e) => {
-                debug!("CORS request to {} is denied: {:?}", request.uri(), e);
-                CorsFutureInner::Handled { headers: None }
+        let result = self.config.process_request(&request);
+
+        let inner = match result {
+            Ok(CorsResource::Preflight(headers)) | Ok(CorsResource::Options(headers)) => {
+                CorsFutureInner::Handled {
+                    headers: Some(headers),
+                }
+            }
+            Ok(CorsResource::Simple(headers)) => {
+                assert!(
+                    self.inner_ready,
+                    "CorsService::call was called before poll_ready"
+                );
+                self.inner_ready = false;
+                CorsFutureInner::Simple {
+                    future: self.inner.call(request),
+                    headers: Some(headers),
+                }
             }
+            Err(_) => CorsFutureInner::Handled { headers: None },
         };
 
         CorsFuture { inner }
@@ -72,11 +100,9 @@ pin_project! {
 
 impl<ReqBody, ResBody, S> Future for CorsFuture<ReqBody, S>
 where
-    ResBody: http_body::Body<Data = Bytes> + Send + Sync + 'static,
-    ResBody::Error: Into<BoxError>,
     S: Service<Request<ReqBody>, Response = Response<ResBody>>,
 {
-    type Output = Result<Response<BoxBody>, S::Error>;
+    type Output = Result<Response<CorsBody<ResBody>>, S::Error>;
     fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.as_mut().project();
         this.inner.poll(ctx)
@@ -98,11 +124,9 @@ pin_project! {
 
 impl<ReqBody, ResBody, S> Future for CorsFutureInner<ReqBody, S>
 where
-    ResBody: http_body::Body<Data = Bytes> + Send + Sync + 'static,
-    ResBody::Error: Into<BoxError>,
     S: Service<Request<ReqBody>, Response = Response<ResBody>>,
 {
-    type Output = Result<Response<BoxBody>, S::Error>;
+    type Output = Result<Response<CorsBody<ResBody>>, S::Error>;
 
     fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.as_mut().project();
@@ -114,13 +138,13 @@ where
                     Ok(mut response) => {
                         let headers = headers.take().expect("poll called twice");
                         response.headers_mut().extend(headers);
-                        Poll::Ready(Ok(response.map(box_body)))
+                        Poll::Ready(Ok(response.map(CorsBody::inner)))
                     }
                     Err(err) => Poll::Ready(Err(err)),
                 }
             }
             CorsFutureInnerProj::Handled { headers } => {
-                let mut response = http::Response::new(http_body::Full::new(Bytes::new()));
+                let mut response = http::Response::new(CorsBody::empty());
                 *response.status_mut() = StatusCode::FORBIDDEN;
 
                 if let Some(headers) = headers.take() {
@@ -128,7 +152,7 @@ where
                     *response.headers_mut() = headers;
                 }
 
-                Poll::Ready(Ok(response.map(box_body)))
+                Poll::Ready(Ok(response))
             }
         }
     }
@@ -0,0 +1,558 @@
+//! The browser side of the CORS protocol, to test a policy by asking
+//! whether a browser would let a page read a response
+//!
+//! `Browser` sends a `Fetch` to a service in-process the way the Fetch
+//! standard specifies and Chromium implements it: it decides whether the
+//! request needs a preflight, sends the preflight and checks its response,
+//! sends the request, runs the CORS check on its response, and filters the
+//! response headers down to those the page can read.
+//!
+//! Only `cors` mode requests are simulated. Preflights are not cached,
+//! redirects are not followed, Fetch Metadata headers are not sent and the
+//! `Range` header is never taken as safelisted.
+//!
+//! https://fetch.spec.whatwg.org/#http-cors-protocol
+
+use super::origin::{url_origin, InvalidOrigin, Origin};
+
+use futures_util::future::poll_fn;
+use http::{
+    header::{self, HeaderName},
+    HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Uri,
+};
+use std::fmt;
+use tower::Service;
+
+/// Whether the request is sent with the cookies of the user, which also
+/// decides whether wildcards are accepted in the response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CredentialsMode {
+    Omit,
+    /// Credentials only for same-origin requests, the default of `fetch`
+    #[default]
+    SameOrigin,
+    Include,
+}
+
+/// A `fetch()` from a page
+#[derive(Debug, Clone)]
+pub struct Fetch {
+    /// The origin of the page, `None` for an opaque origin
+    origin: Option<Origin>,
+    method: Method,
+    uri: Uri,
+    target: Origin,
+    /// The `Host` header, the authority of the URL
+    host: HeaderValue,
+    headers: HeaderMap,
+    credentials: CredentialsMode,
+}
+
+/// The outcome of a `Fetch`, as the page would observe it
+#[derive(Debug, Clone)]
+pub struct Verdict {
+    /// Whether a preflight was sent before the request
+    pub preflighted: bool,
+    pub outcome: Result<Exposed, Blocked>,
+}
+
+/// The part of the response the page can read
+#[derive(Debug, Clone)]
+pub struct Exposed {
+    pub status: StatusCode,
+    /// The response headers visible to the page
+    pub headers: HeaderMap,
+}
+
+/// A network error, which is all the page learns of a blocked fetch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blocked {
+    pub stage: Stage,
+    pub reason: BlockReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Preflight,
+    Request,
+}
+
+/// Why the browser blocked the response, as reported in its console
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockReason {
+    /// The preflight was not answered with a 2xx status
+    PreflightStatus(StatusCode),
+    MissingAllowOrigin,
+    /// `Access-Control-Allow-Origin` was sent more than once
+    MultipleAllowOrigin,
+    /// `Access-Control-Allow-Origin: *` for a request with credentials
+    WildcardOrigin,
+    /// The allowed origin, which is not the origin of the page byte for byte
+    OriginMismatch(HeaderValue),
+    /// A request with credentials was not answered with
+    /// `Access-Control-Allow-Credentials: true`
+    MissingAllowCredentials,
+    /// The header is not a valid comma separated list
+    InvalidHeader(HeaderName),
+    MethodNotAllowed(Method),
+    HeaderNotAllowed(HeaderName),
+    /// The service returned this error instead of a response
+    Service(String),
+}
+
+/// Sends `Fetch`es to a service, e.g. a `CorsService`, the way a browser
+/// would
+#[derive(Debug, Clone)]
+pub struct Browser<S> {
+    service: S,
+}
+
+impl Fetch {
+    /// A `fetch(url, { method })` from a page of `origin`, which is `null`
+    /// for an opaque origin, e.g. of a sandboxed iframe. The URL has to be
+    /// absolute.
+    pub fn new(origin: &str, method: Method, url: &str) -> Result<Fetch, InvalidOrigin> {
+        let origin = match origin {
+            "null" => None,
+            origin => Some(Origin::parse(origin)?),
+        };
+        let target = url_origin(url).ok_or(InvalidOrigin::Syntax)?;
+        // The fragment stays in the page, it is not sent
+        let url = url.split('#').next().unwrap_or_default();
+        let uri = url.parse::<Uri>().map_err(|_| InvalidOrigin::Syntax)?;
+        let host = uri
+            .authority()
+            .and_then(|authority| HeaderValue::from_str(authority.as_str()).ok())
+            .ok_or(InvalidOrigin::Syntax)?;
+
+        Ok(Fetch {
+            origin,
+            method: normalize_method(method),
+            uri,
+            target,
+            host,
+            headers: HeaderMap::new(),
+            credentials: CredentialsMode::default(),
+        })
+    }
+
+    /// Add a request header. As in a browser, forbidden request headers
+    /// such as `Origin` or `Cookie` are silently dropped.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        if !is_forbidden_request_header(&name) {
+            self.headers.append(name, value);
+        }
+        self
+    }
+
+    pub fn credentials(mut self, credentials: CredentialsMode) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    pub fn is_cross_origin(&self) -> bool {
+        self.origin.as_ref() != Some(&self.target)
+    }
+
+    /// Whether a browser sends a preflight before this request: it is
+    /// cross-origin and its method or one of its headers is not safelisted
+    pub fn needs_preflight(&self) -> bool {
+        self.is_cross_origin()
+            && (!is_safelisted_method(&self.method) || !self.unsafe_header_names().is_empty())
+    }
+
+    /// The serialization of the origin of the page
+    fn origin_header(&self) -> HeaderValue {
+        match self.origin {
+            Some(ref origin) => origin.to_header_value(),
+            None => HeaderValue::from_static("null"),
+        }
+    }
+
+    /// The sorted, lowercase names of the headers which are not
+    /// safelisted, as sent in `Access-Control-Request-Headers`
+    fn unsafe_header_names(&self) -> Vec<HeaderName> {
+        let mut safelisted_size = 0;
+        let mut safelisted = Vec::new();
+        let mut names = Vec::new();
+        for name in self.headers.keys() {
+            let value = combined_value(&self.headers, name);
+            if is_safelisted_request_header(name, &value) {
+                safelisted_size += value.len();
+                safelisted.push(name.clone());
+            } else {
+                names.push(name.clone());
+            }
+        }
+        // Safelisted headers larger than 1024 bytes in total are unsafe
+        if safelisted_size > 1024 {
+            names.extend(safelisted);
+        }
+        names.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        names
+    }
+
+    fn request<B: Default>(&self, method: Method) -> Request<B> {
+        let mut request = Request::new(B::default());
+        *request.method_mut() = method;
+        *request.uri_mut() = self.uri.clone();
+        request
+            .headers_mut()
+            .insert(header::HOST, self.host.clone());
+        request
+    }
+
+    fn preflight<B: Default>(&self) -> Request<B> {
+        let mut request = self.request(Method::OPTIONS);
+        let headers = request.headers_mut();
+        headers.insert(header::ACCEPT, HeaderValue::from_static("*/*"));
+        headers.insert(header::ORIGIN, self.origin_header());
+        headers.insert(
+            header::ACCESS_CONTROL_REQUEST_METHOD,
+            HeaderValue::from_str(self.method.as_str()).expect("method is a valid header"),
+        );
+        let names = self.unsafe_header_names();
+        if !names.is_empty() {
+            let names = names
+                .iter()
+                .map(HeaderName::as_str)
+                .collect::<Vec<_>>()
+                .join(",");
+            headers.insert(
+                header::ACCESS_CONTROL_REQUEST_HEADERS,
+                HeaderValue::from_str(&names).expect("header names are a valid header"),
+            );
+        }
+        request
+    }
+
+    fn actual_request<B: Default>(&self) -> Request<B> {
+        let mut request = self.request(self.method.clone());
+        let headers = request.headers_mut();
+        headers.extend(self.headers.clone());
+        // Same-origin requests only carry the origin for unsafe methods
+        if self.is_cross_origin() || !(self.method == Method::GET || self.method == Method::HEAD) {
+            headers.insert(header::ORIGIN, self.origin_header());
+        }
+        request
+    }
+
+    /// The CORS check of a response to this request
+    fn cors_check(&self, headers: &HeaderMap) -> Result<(), BlockReason> {
+        let allow_origin = single_value(headers, &header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .ok_or(BlockReason::MissingAllowOrigin)??;
+        let include = self.credentials == CredentialsMode::Include;
+        if allow_origin == "*" {
+            return if include {
+                Err(BlockReason::WildcardOrigin)
+            } else {
+                Ok(())
+            };
+        }
+        if allow_origin.as_bytes() != self.origin_header().as_bytes() {
+            return Err(BlockReason::OriginMismatch(allow_origin.clone()));
+        }
+        if !include {
+            return Ok(());
+        }
+        match single_value(headers, &header::ACCESS_CONTROL_ALLOW_CREDENTIALS) {
+            Some(Ok(value)) if value == "true" => Ok(()),
+            _ => Err(BlockReason::MissingAllowCredentials),
+        }
+    }
+
+    /// The checks of the preflight response after the CORS check: the
+    /// method and every unsafe header have to be allowed
+    fn preflight_check(&self, headers: &HeaderMap) -> Result<(), BlockReason> {
+        let methods = header_list(headers, &header::ACCESS_CONTROL_ALLOW_METHODS)?;
+        let names = header_list(headers, &header::ACCESS_CONTROL_ALLOW_HEADERS)?;
+        let include = self.credentials == CredentialsMode::Include;
+
+        // Methods are compared case-sensitively, after the normalization
+        // of the standard methods in `Fetch::new`
+        let method_allowed = is_safelisted_method(&self.method)
+            || methods.iter().any(|m| m == self.method.as_str())
+            || (!include && methods.iter().any(|m| m == "*"));
+        if !method_allowed {
+            return Err(BlockReason::MethodNotAllowed(self.method.clone()));
+        }
+
+        let wildcard = !include && names.iter().any(|name| name == "*");
+        for name in self.unsafe_header_names() {
+            let listed = names.iter().any(|n| n.eq_ignore_ascii_case(name.as_str()));
+            // The wildcard never covers `Authorization`
+            if !listed && (!wildcard || name == header::AUTHORIZATION) {
+                return Err(BlockReason::HeaderNotAllowed(name));
+            }
+        }
+        Ok(())
+    }
+
+    /// The headers of a response to a cross-origin request which the page
+    /// can read
+    fn exposed_headers(&self, headers: &HeaderMap) -> HeaderMap {
+        let exposed =
+            header_list(headers, &header::ACCESS_CONTROL_EXPOSE_HEADERS).unwrap_or_default();
+        let all = self.credentials != CredentialsMode::Include && exposed.iter().any(|n| n == "*");
+        filter_headers(headers, |name| {
+            all || is_safelisted_response_header(name)
+                || exposed
+                    .iter()
+                    .any(|n| n.eq_ignore_ascii_case(name.as_str()))
+        })
+    }
+}
+
+impl Verdict {
+    pub fn is_allowed(&self) -> bool {
+        self.outcome.is_ok()
+    }
+
+    pub fn exposed(&self) -> Option<&Exposed> {
+        self.outcome.as_ref().ok()
+    }
+
+    pub fn blocked(&self) -> Option<&Blocked> {
+        self.outcome.as_ref().err()
+    }
+}
+
+impl<S> Browser<S> {
+    pub fn new(service: S) -> Browser<S> {
+        Browser { service }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.service
+    }
+
+    /// Send the fetch, preceded by a preflight if a browser would send one
+    pub async fn fetch<B, R>(&mut self, fetch: &Fetch) -> Verdict
+    where
+        S: Service<Request<B>, Response = Response<R>>,
+        S::Error: fmt::Display,
+        B: Default,
+    {
+        let preflighted = fetch.needs_preflight();
+        let outcome = self.run(fetch, preflighted).await;
+        Verdict {
+            preflighted,
+            outcome,
+        }
+    }
+
+    async fn run<B, R>(&mut self, fetch: &Fetch, preflight: bool) -> Result<Exposed, Blocked>
+    where
+        S: Service<Request<B>, Response = Response<R>>,
+        S::Error: fmt::Display,
+        B: Default,
+    {
+        if preflight {
+            let blocked = |reason| Blocked {
+                stage: Stage::Preflight,
+                reason,
+            };
+            let response = self.send(fetch.preflight()).await.map_err(blocked)?;
+            if !response.status().is_success() {
+                return Err(blocked(BlockReason::PreflightStatus(response.status())));
+            }
+            fetch.cors_check(response.headers()).map_err(blocked)?;
+            fetch.preflight_check(response.headers()).map_err(blocked)?;
+        }
+
+        let blocked = |reason| Blocked {
+            stage: Stage::Request,
+            reason,
+        };
+        let response = self.send(fetch.actual_request()).await.map_err(blocked)?;
+        let headers = if fetch.is_cross_origin() {
+            fetch.cors_check(response.headers()).map_err(blocked)?;
+            fetch.exposed_headers(response.headers())
+        } else {
+            filter_headers(response.headers(), |_| true)
+        };
+        Ok(Exposed {
+            status: response.status(),
+            headers,
+        })
+    }
+
+    async fn send<B, R>(&mut self, request: Request<B>) -> Result<Response<R>, BlockReason>
+    where
+        S: Service<Request<B>, Response = Response<R>>,
+        S::Error: fmt::Display,
+    {
+        let service_error = |err: S::Error| BlockReason::Service(err.to_string());
+        poll_fn(|cx| self.service.poll_ready(cx))
+            .await
+            .map_err(service_error)?;
+        self.service.call(request).await.map_err(service_error)
+    }
+}
+
+/// Browsers uppercase the standard methods, any other method is sent as
+/// written
+fn normalize_method(method: Method) -> Method {
+    const NORMALIZED: [Method; 6] = [
+        Method::DELETE,
+        Method::GET,
+        Method::HEAD,
+        Method::OPTIONS,
+        Method::POST,
+        Method::PUT,
+    ];
+    NORMALIZED
+        .iter()
+        .find(|m| m.as_str().eq_ignore_ascii_case(method.as_str()))
+        .cloned()
+        .unwrap_or(method)
+}
+
+/// https://fetch.spec.whatwg.org/#cors-safelisted-method
+fn is_safelisted_method(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::POST)
+}
+
+/// https://fetch.spec.whatwg.org/#cors-safelisted-request-header
+fn is_safelisted_request_header(name: &HeaderName, value: &[u8]) -> bool {
+    if value.len() > 128 {
+        return false;
+    }
+    match name.as_str() {
+        "accept" => !value.iter().copied().any(is_unsafe_byte),
+        "accept-language" | "content-language" => value
+            .iter()
+            .all(|&b| b.is_ascii_alphanumeric() || b" *,-.;=".contains(&b)),
+        "content-type" => {
+            if value.iter().copied().any(is_unsafe_byte) {
+                return false;
+            }
+            let essence = match std::str::from_utf8(value) {
+                Ok(value) => value.split(';').next().unwrap_or_default().trim(),
+                Err(_) => return false,
+            };
+            [
+                "application/x-www-form-urlencoded",
+                "multipart/form-data",
+                "text/plain",
+            ]
+            .iter()
+            .any(|mime| essence.eq_ignore_ascii_case(mime))
+        }
+        _ => false,
+    }
+}
+
+/// https://fetch.spec.whatwg.org/#cors-unsafe-request-header-byte
+fn is_unsafe_byte(b: u8) -> bool {
+    (b < 0x20 && b != b'\t') || b == 0x7f || b"\"():<>?@[\\]{}".contains(&b)
+}
+
+/// https://fetch.spec.whatwg.org/#forbidden-request-header
+fn is_forbidden_request_header(name: &HeaderName) -> bool {
+    const FORBIDDEN: [&str; 21] = [
+        "accept-charset",
+        "accept-encoding",
+        "access-control-request-headers",
+        "access-control-request-method",
+        "connection",
+        "content-length",
+        "cookie",
+        "cookie2",
+        "date",
+        "dnt",
+        "expect",
+        "host",
+        "keep-alive",
+        "origin",
+        "referer",
+        "set-cookie",
+        "te",
+        "trailer",
+        "transfer-encoding",
+        "upgrade",
+        "via",
+    ];
+    let name = name.as_str();
+    FORBIDDEN.contains(&name) || name.starts_with("proxy-") || name.starts_with("sec-")
+}
+
+/// https://fetch.spec.whatwg.org/#cors-safelisted-response-header-name
+fn is_safelisted_response_header(name: &HeaderName) -> bool {
+    matches!(
+        name.as_str(),
+        "cache-control"
+            | "content-language"
+            | "content-length"
+            | "content-type"
+            | "expires"
+            | "last-modified"
+            | "pragma"
+    )
+}
+
+/// The headers matching `filter`, without `Set-Cookie` which a page can
+/// never read
+fn filter_headers(headers: &HeaderMap, filter: impl Fn(&HeaderName) -> bool) -> HeaderMap {
+    let mut filtered = HeaderMap::new();
+    for (name, value) in headers {
+        if name != header::SET_COOKIE && name.as_str() != "set-cookie2" && filter(name) {
+            filtered.append(name.clone(), value.clone());
+        }
+    }
+    filtered
+}
+
+/// The value of a header which may only be sent once, `Err` if it was sent
+/// more than once: its lines would be combined into a value which cannot
+/// match
+fn single_value<'a>(
+    headers: &'a HeaderMap,
+    name: &HeaderName,
+) -> Option<Result<&'a HeaderValue, BlockReason>> {
+    let mut values = headers.get_all(name).iter();
+    let value = values.next()?;
+    if values.next().is_some() {
+        return Some(Err(if name == header::ACCESS_CONTROL_ALLOW_ORIGIN {
+            BlockReason::MultipleAllowOrigin
+        } else {
+            BlockReason::InvalidHeader(name.clone())
+        }));
+    }
+    Some(Ok(value))
+}
+
+/// The lines of a header combined into one value, as a browser sees them
+fn combined_value(headers: &HeaderMap, name: &HeaderName) -> Vec<u8> {
+    let mut combined = Vec::new();
+    for value in headers.get_all(name) {
+        if !combined.is_empty() {
+            combined.extend_from_slice(b", ");
+        }
+        combined.extend_from_slice(value.as_bytes());
+    }
+    combined
+}
+
+/// The items of a comma separated header over all its lines, each of which
+/// has to be a token
+fn header_list(headers: &HeaderMap, name: &HeaderName) -> Result<Vec<String>, BlockReason> {
+    let mut items = Vec::new();
+    for value in headers.get_all(name) {
+        let value = value
+            .to_str()
+            .map_err(|_| BlockReason::InvalidHeader(name.clone()))?;
+        for item in value.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            if !item.bytes().all(is_token_byte) {
+                return Err(BlockReason::InvalidHeader(name.clone()));
+            }
+            items.push(item.to_string());
+        }
+    }
+    Ok(items)
+}
+
+/// https://httpwg.org/specs/rfc9110.html#tokens
+fn is_token_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
@@ -0,0 +1,54 @@
+//! `tracing` instrumentation for CORS decisions, enabled by the `tracing` feature.
+
+use super::config::{CorsResource, InvalidRequest, RequestKind};
+
+use http::{header, HeaderValue, Request};
+use tracing::{field, Level, Span};
+
+pub(crate) fn request_span<B>(request: &Request<B>, kind: RequestKind) -> Span {
+    let origin = request.headers().get(header::ORIGIN).map(origin_field);
+    tracing::debug_span!(
+        "cors",
+        origin = origin.unwrap_or(""),
+        kind = kind.as_str(),
+        outcome = field::Empty,
+        reason = field::Empty,
+    )
+}
+
+pub(crate) fn record_outcome(span: &Span, result: &Result<CorsResource, InvalidRequest>) {
+    match result {
+        Ok(_) => {
+            span.record("outcome", "allowed");
+        }
+        Err(e) => {
+            span.record("outcome", "denied");
+            span.record("reason", e.reason());
+        }
+    }
+}
+
+macro_rules! denial_event {
+    ($level:expr, $request:expr, $e:expr) => {
+        tracing::event!(
+            $level,
+            uri = %$request.uri(),
+            reason = $e.reason(),
+            "CORS request denied"
+        )
+    };
+}
+
+pub(crate) fn denial_event<B>(level: log::Level, request: &Request<B>, e: &InvalidRequest) {
+    match level {
+        log::Level::Error => denial_event!(Level::ERROR, request, e),
+        log::Level::Warn => denial_event!(Level::WARN, request, e),
+        log::Level::Info => denial_event!(Level::INFO, request, e),
+        log::Level::Debug => denial_event!(Level::DEBUG, request, e),
+        log::Level::Trace => denial_event!(Level::TRACE, request, e),
+    }
+}
+
+fn origin_field(origin: &HeaderValue) -> &str {
+    origin.to_str().unwrap_or("<non-ascii>")
+}
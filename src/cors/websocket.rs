@@ -0,0 +1,81 @@
+//! Origin enforcement for WebSocket handshakes
+//!
+//! Browsers do not apply CORS to WebSocket connections: any page can open
+//! one to any server, with the cookies of the user. The server has to check
+//! the `Origin` of the handshake itself to prevent cross-site WebSocket
+//! hijacking.
+//!
+//! https://datatracker.ietf.org/doc/html/rfc6455#section-10.2
+//!
+//! Only HTTP/1.1 handshakes are detected. WebSockets over HTTP/2 are opened
+//! with an extended `CONNECT` (RFC 8441), whose `:protocol` pseudo-header
+//! is only exposed through the extensions of the HTTP implementation. Such
+//! a request is checked as a regular CORS request instead, against the CORS
+//! allowlist rather than `WebSocketProtection::allow_origins`. axum 0.7
+//! does not accept them either.
+
+use super::config::AllowedOrigins;
+
+use http::{header, Request};
+
+/// The origin policy for WebSocket handshakes
+#[derive(Debug, Clone, Default)]
+pub struct WebSocketProtection {
+    allowed_origins: Option<AllowedOrigins>,
+    allow_missing_origin: bool,
+}
+
+impl WebSocketProtection {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The origins allowed to open a WebSocket, instead of the CORS
+    /// allowlist
+    pub fn allow_origins(mut self, origins: AllowedOrigins) -> Self {
+        self.allowed_origins = Some(origins);
+        self
+    }
+
+    /// Whether handshakes without `Origin`, which clients other than
+    /// browsers send, are allowed. Defaults to `false`.
+    pub fn allow_missing_origin(mut self, allow_missing_origin: bool) -> Self {
+        self.allow_missing_origin = allow_missing_origin;
+        self
+    }
+
+    /// The origins allowed to open a WebSocket, `None` for the CORS
+    /// allowlist
+    pub fn allowed_origins(&self) -> Option<&AllowedOrigins> {
+        self.allowed_origins.as_ref()
+    }
+
+    pub fn allows_missing_origin(&self) -> bool {
+        self.allow_missing_origin
+    }
+
+    /// Normalize the configured origins, skipping invalid ones, see
+    /// `AllowedOrigins::normalized`
+    pub(crate) fn normalized(self) -> Self {
+        WebSocketProtection {
+            allowed_origins: self.allowed_origins.map(AllowedOrigins::normalized_or_skip),
+            ..self
+        }
+    }
+}
+
+/// Whether the request is an HTTP/1.1 WebSocket handshake. An HTTP/2
+/// extended `CONNECT` is not detected, see the module documentation.
+pub fn is_websocket_upgrade<B>(request: &Request<B>) -> bool {
+    let headers = request.headers();
+    let upgrade = headers
+        .get(header::UPGRADE)
+        .is_some_and(|upgrade| upgrade.as_bytes().eq_ignore_ascii_case(b"websocket"));
+    let connection = headers.get_all(header::CONNECTION).iter().any(|value| {
+        value
+            .as_bytes()
+            .split(|&b| b == b',')
+            .any(|token| token.trim_ascii().eq_ignore_ascii_case(b"upgrade"))
+    });
+    upgrade && connection
+}
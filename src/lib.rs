@@ -15,10 +15,24 @@ mod test {
 
     pub use super::builder::*;
     pub use super::config::*;
+    pub use super::csrf::*;
+    pub use super::fetch_metadata::*;
+    pub use super::index::*;
+    pub use super::isolation::*;
+    #[cfg(feature = "tower")]
     pub use super::layer::*;
+    pub use super::methods::*;
+    #[cfg(feature = "metrics")]
+    pub use super::metrics::*;
+    pub use super::origin::*;
+    pub use super::pattern::*;
+    pub use super::routes::*;
+    pub use super::same_origin::*;
+    #[cfg(feature = "tower")]
     pub use super::service::*;
+    pub use super::websocket::*;
 
-    type TestError = Box<dyn (::std::error::Error)>;
+    type TestError = Box<dyn ::std::error::Error>;
     type TestResult<T = ()> = ::std::result::Result<T, TestError>;
 
     macro_rules! assert_variant {
@@ -172,6 +186,61 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn simple_response_includes_timing_allow_origin() -> TestResult {
+        let builder = CorsBuilder::new()
+            .allow_origins(AllowedOrigins::from_iter(vec![HeaderValue::from_static(
+                "http://test.example",
+            )]))
+            .allow_methods(vec![Method::POST]);
+
+        let req = http::Request::builder()
+            .header(
+                header::ORIGIN,
+                HeaderValue::from_static("http://test.example"),
+            )
+            .body(())?;
+
+        let headers = builder
+            .clone()
+            .into_config()
+            .process_request(&req)?
+            .into_simple()?;
+        assert!(!headers.contains_key(TIMING_ALLOW_ORIGIN));
+
+        // Independent of the wildcard in Access-Control-Allow-Origin
+        let cfg = builder
+            .clone()
+            .prefer_wildcard(true)
+            .timing_allow_origin(TimingAllowOrigin::Reflect)
+            .into_config();
+        let headers = cfg.process_request(&req)?.into_simple()?;
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert_eq!(headers[TIMING_ALLOW_ORIGIN], "http://test.example");
+
+        let cfg = builder
+            .timing_allow_origin(TimingAllowOrigin::Wildcard)
+            .into_config();
+        let headers = cfg.process_request(&req)?.into_simple()?;
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://test.example"
+        );
+        assert_eq!(headers[TIMING_ALLOW_ORIGIN], "*");
+
+        // Only actual responses carry the timing of the resource
+        let preflight = preflight_origin_request_builder()?
+            .header(
+                header::ORIGIN,
+                HeaderValue::from_static("http://test.example"),
+            )
+            .body(())?;
+        let headers = cfg.process_request(&preflight)?.into_preflight()?;
+        assert!(!headers.contains_key(TIMING_ALLOW_ORIGIN));
+
+        Ok(())
+    }
+
     #[test]
     fn preflight_allows_when_origin_is_any() -> TestResult {
         common_allows_when_origin_is_any(
@@ -435,7 +504,50 @@ mod test {
             .remove(header::ACCESS_CONTROL_ALLOW_METHODS)
             .expect("allow-methods header missing");
 
-        assert_set!(hdr, "PATCH", "LIST", "POST");
+        assert_eq!(hdr, "POST,PATCH,LIST");
+
+        Ok(())
+    }
+
+    #[test]
+    fn method_set_keeps_canonical_order() -> TestResult {
+        let mut methods = MethodSet::from_iter(vec![
+            Method::from_bytes(b"PROPFIND")?,
+            Method::PATCH,
+            Method::DELETE,
+            Method::from_bytes(b"LIST")?,
+            Method::GET,
+        ]);
+        assert!(!methods.insert(Method::GET));
+        assert!(!methods.insert(Method::from_bytes(b"LIST")?));
+        assert!(methods.insert(Method::HEAD));
+
+        assert_eq!(methods.len(), 6);
+        assert!(methods.contains(&Method::PATCH));
+        assert!(methods.contains(&Method::from_bytes(b"PROPFIND")?));
+        assert!(!methods.contains(&Method::PUT));
+        assert!(!methods.contains(&Method::from_bytes(b"list")?));
+        assert_eq!(
+            methods.to_header_value(),
+            "GET,HEAD,DELETE,PATCH,LIST,PROPFIND"
+        );
+
+        let standard = MethodSet::from_iter(vec![
+            Method::PATCH,
+            Method::TRACE,
+            Method::OPTIONS,
+            Method::CONNECT,
+            Method::DELETE,
+            Method::PUT,
+            Method::POST,
+            Method::HEAD,
+            Method::GET,
+        ]);
+        assert_eq!(standard.len(), 9);
+        assert_eq!(
+            standard.to_header_value(),
+            "GET,HEAD,POST,PUT,DELETE,CONNECT,OPTIONS,TRACE,PATCH"
+        );
 
         Ok(())
     }
@@ -504,199 +616,2851 @@ mod test {
         Ok(())
     }
 
-    fn common_allows_when_origin_is_any(
-        cfg_builder: CorsBuilder,
-        req_builder: impl Fn() -> TestResult<http::request::Builder>,
-    ) -> TestResult {
-        let cfg = cfg_builder
-            .allow_origins(AllowedOrigins::Any { allow_null: false })
+    #[test]
+    fn denial_level_defaults_to_debug() -> TestResult {
+        let cfg = CorsBuilder::new().into_config();
+        assert_eq!(cfg.denial_level, log::Level::Debug);
+
+        let cfg = CorsBuilder::new()
+            .denial_level(log::Level::Warn)
             .into_config();
+        assert_eq!(cfg.denial_level, log::Level::Warn);
 
-        let req = req_builder()?
+        Ok(())
+    }
+
+    #[test]
+    fn request_kind_classifies_requests() -> TestResult {
+        let no_origin = http::Request::builder().body(())?;
+        assert_eq!(RequestKind::of(&no_origin), RequestKind::NoOrigin);
+
+        let simple = http::Request::builder()
+            .method(Method::OPTIONS)
             .header(
                 header::ORIGIN,
                 HeaderValue::from_static("http://test.example"),
             )
             .body(())?;
+        assert_eq!(RequestKind::of(&simple), RequestKind::Simple);
 
-        assert_variant!(cfg.process_request(&req), Ok(_));
+        let preflight = preflight_origin_request_builder()?
+            .header(
+                header::ORIGIN,
+                HeaderValue::from_static("http://test.example"),
+            )
+            .body(())?;
+        assert_eq!(RequestKind::of(&preflight), RequestKind::Preflight);
 
         Ok(())
     }
 
-    fn common_disallows_null_origin_even_for_any(
-        cfg_builder: CorsBuilder,
-        req_builder: impl Fn() -> TestResult<http::request::Builder>,
-    ) -> TestResult {
-        let cfg = cfg_builder
-            .allow_origins(AllowedOrigins::Any { allow_null: false })
-            .into_config();
+    #[cfg(all(feature = "metrics", feature = "tower"))]
+    #[tokio::test]
+    async fn metrics_count_requests_and_denials() -> TestResult {
+        use std::{convert::Infallible, sync::Arc};
+        use tower::{service_fn, Layer, ServiceExt};
+
+        let recorder = Arc::new(InMemoryRecorder::new());
+        let service = CorsBuilder::new()
+            .allow_origins(AllowedOrigins::from_iter(vec![HeaderValue::from_static(
+                "http://foo.example",
+            )]))
+            .allow_methods(vec![Method::POST])
+            .metrics(CorsMetrics::new(recorder.clone()).origin_label(OriginLabel::TopN(1)))
+            .into_layer()
+            .layer(service_fn(|_: http::Request<()>| async {
+                Ok::<_, Infallible>(http::Response::new(String::new()))
+            }));
+
+        let requests = vec![
+            http::Request::builder().body(())?,
+            preflight_origin_request_builder()?
+                .header(header::ORIGIN, "http://foo.example")
+                .body(())?,
+            http::Request::builder()
+                .header(header::ORIGIN, "http://bar.example")
+                .body(())?,
+            http::Request::builder()
+                .header(header::ORIGIN, "http://quux.example")
+                .body(())?,
+            http::Request::builder()
+                .header(header::ORIGIN, "http://bar.example")
+                .body(())?,
+        ];
+        for req in requests {
+            service.clone().oneshot(req).await?;
+        }
 
-        let req = req_builder()?
-            .header(header::ORIGIN, HeaderValue::from_static("null"))
-            .body(())?;
+        assert_eq!(
+            recorder.counter(REQUESTS_TOTAL, &[("kind", "no_origin")]),
+            1
+        );
+        assert_eq!(
+            recorder.counter(REQUESTS_TOTAL, &[("kind", "preflight")]),
+            1
+        );
+        assert_eq!(recorder.counter(REQUESTS_TOTAL, &[("kind", "simple")]), 3);
+        assert_eq!(recorder.total(DENIED_TOTAL), 3);
+
+        let denied = |origin| {
+            recorder.counter(
+                DENIED_TOTAL,
+                &[
+                    ("kind", "simple"),
+                    ("reason", "disallowed_origin"),
+                    ("origin", origin),
+                ],
+            )
+        };
+        assert_eq!(denied("http://bar.example"), 2);
+        assert_eq!(denied(OTHER_ORIGIN), 1);
 
-        assert_variant!(cfg.process_request(&req), Err(_disallowed_origin));
+        assert!(recorder
+            .render()
+            .contains("cors_requests_total{kind=\"simple\"} 3"));
 
         Ok(())
     }
 
-    fn common_allows_null_origin_for_any_when_configured(
-        cfg_builder: CorsBuilder,
-        req_builder: impl Fn() -> TestResult<http::request::Builder>,
-    ) -> TestResult {
-        let cfg = cfg_builder
-            .allow_origins(AllowedOrigins::Any { allow_null: true })
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics_report_the_most_frequent_origins() -> TestResult {
+        use std::sync::Arc;
+
+        let recorder = Arc::new(InMemoryRecorder::new());
+        let cfg = CorsBuilder::new()
+            .metrics(CorsMetrics::new(recorder.clone()).origin_label(OriginLabel::TopN(1)))
             .into_config();
+        let denied_from = |origin: &'static str| -> TestResult {
+            let req = http::Request::builder()
+                .header(header::ORIGIN, origin)
+                .body(())?;
+            assert!(cfg.process_request(&req).is_err());
+            Ok(())
+        };
+        let denied = |origin| {
+            recorder.counter(
+                DENIED_TOTAL,
+                &[
+                    ("kind", "simple"),
+                    ("reason", "disallowed_origin"),
+                    ("origin", origin),
+                ],
+            )
+        };
 
-        let req = req_builder()?
-            .header(header::ORIGIN, HeaderValue::from_static("null"))
-            .body(())?;
+        // The first origin is the most frequent one
+        denied_from("http://first.example")?;
+        assert_eq!(denied("http://first.example"), 1);
 
-        assert_variant!(cfg.process_request(&req), Ok(_));
+        // Ties are won by the origin already reported
+        denied_from("http://frequent.example")?;
+        assert_eq!(denied(OTHER_ORIGIN), 1);
+
+        // until another origin becomes more frequent
+        denied_from("http://frequent.example")?;
+        denied_from("http://first.example")?;
+        assert_eq!(denied("http://frequent.example"), 1);
+        assert_eq!(denied(OTHER_ORIGIN), 2);
 
         Ok(())
     }
 
-    fn common_compares_origin_against_allowed_origins(
-        cfg_builder: CorsBuilder,
-        req_builder: impl Fn() -> TestResult<http::request::Builder>,
-    ) -> TestResult {
-        let cfg = cfg_builder
-            .allow_origins(AllowedOrigins::from_iter(vec![
-                HeaderValue::from_static("http://foo.example"),
-                HeaderValue::from_static("http://bar.example"),
-            ]))
-            .into_config();
+    #[test]
+    fn origin_parse_normalizes() -> TestResult {
+        let cases = vec![
+            ("https://Example.COM", "https://example.com"),
+            ("https://example.com:443", "https://example.com"),
+            ("https://example.com/", "https://example.com"),
+            ("HTTP://example.com:80/", "http://example.com"),
+            ("http://example.com:443", "http://example.com:443"),
+            ("https://bücher.example", "https://xn--bcher-kva.example"),
+            ("http://[0:0:0:0:0:0:0:1]:8080", "http://[::1]:8080"),
+            ("http://127.0.0.1:3000", "http://127.0.0.1:3000"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(Origin::parse(input)?.to_string(), expected, "{}", input);
+        }
 
-        let allowed_req_foo = req_builder()?
-            .header(
-                header::ORIGIN,
-                HeaderValue::from_static("http://foo.example"),
-            )
-            .body(())?;
+        Ok(())
+    }
 
-        assert_variant!(cfg.process_request(&allowed_req_foo), Ok(_));
+    #[test]
+    fn origin_parse_rejects_malformed() {
+        let cases = vec![
+            ("example.com", InvalidOrigin::Syntax),
+            ("1http://example.com", InvalidOrigin::InvalidScheme),
+            ("https://", InvalidOrigin::InvalidHost),
+            ("https://exa mple.com", InvalidOrigin::InvalidHost),
+            ("https://example.com:", InvalidOrigin::InvalidPort),
+            ("https://example.com:99999", InvalidOrigin::InvalidPort),
+            (
+                "https://example.com/path",
+                InvalidOrigin::UnexpectedComponent,
+            ),
+            ("https://example.com?q", InvalidOrigin::UnexpectedComponent),
+            ("https://example.com#f", InvalidOrigin::UnexpectedComponent),
+            (
+                "https://user@example.com",
+                InvalidOrigin::UnexpectedComponent,
+            ),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(Origin::parse(input), Err(expected), "{}", input);
+        }
+    }
 
-        let allowed_req_bar = req_builder()?
-            .header(
-                header::ORIGIN,
-                HeaderValue::from_static("http://bar.example"),
-            )
-            .body(())?;
+    #[test]
+    fn allowed_origins_are_normalized() -> TestResult {
+        let cfg = CorsBuilder::new()
+            .allow_origins(AllowedOrigins::from_iter(vec![
+                HeaderValue::from_static("https://Example.com:443/"),
+                HeaderValue::from_bytes("https://bücher.example".as_bytes())?,
+            ]))
+            .into_config();
 
-        assert_variant!(cfg.process_request(&allowed_req_bar), Ok(_));
+        for origin in &["https://example.com", "https://xn--bcher-kva.example"] {
+            let req = http::Request::builder()
+                .header(header::ORIGIN, *origin)
+                .body(())?;
+            assert_variant!(cfg.process_request(&req), Ok(_));
+        }
 
-        let disallowed_req_unlisted = req_builder()?
-            .header(
-                header::ORIGIN,
-                HeaderValue::from_static("http://quux.example"),
-            )
+        let malformed = http::Request::builder()
+            .header(header::ORIGIN, "example.com")
             .body(())?;
-
         assert_variant!(
-            cfg.process_request(&disallowed_req_unlisted),
-            Err(_disallowed_origin)
+            cfg.process_request(&malformed),
+            Err(InvalidRequest::InvalidOrigin(InvalidOrigin::Syntax))
         );
 
-        let disallowed_req_differing_case = req_builder()?
-            .header(
-                header::ORIGIN,
-                HeaderValue::from_static("http://FOO.example"),
-            )
+        // Unlike configured origins, request origins have no trailing slash
+        let trailing_slash = http::Request::builder()
+            .header(header::ORIGIN, "https://example.com/")
             .body(())?;
-
         assert_variant!(
-            cfg.process_request(&disallowed_req_differing_case),
-            Err(_disallowed_origin)
+            cfg.process_request(&trailing_slash),
+            Err(InvalidRequest::InvalidOrigin(
+                InvalidOrigin::UnexpectedComponent
+            ))
         );
 
-        let disallowed_req_differing_scheme = req_builder()?
-            .header(
-                header::ORIGIN,
-                HeaderValue::from_static("https://foo.example"),
-            )
+        // Invalid origins are skipped, or reported by `try_into_config`
+        let builder = CorsBuilder::new().allow_origins(AllowedOrigins::from_iter(vec![
+            HeaderValue::from_static("https://example.com/path"),
+            HeaderValue::from_static("https://example.com"),
+        ]));
+        assert_variant!(
+            builder.clone().try_into_config(),
+            Err(InvalidOrigin::UnexpectedComponent)
+        );
+        let cfg = builder.into_config();
+        let req = http::Request::builder()
+            .header(header::ORIGIN, "https://example.com")
             .body(())?;
+        assert_variant!(cfg.process_request(&req), Ok(_));
 
-        assert_variant!(
-            cfg.process_request(&disallowed_req_differing_scheme),
-            Err(_disallowed_origin)
+        assert!(
+            AllowedOrigins::try_from_origins(vec![HeaderValue::from_static(
+                "https://example.com/path"
+            )])
+            .is_err()
         );
 
         Ok(())
     }
 
-    fn common_test_vary_header<B>(
-        builder: CorsBuilder,
-        req: http::Request<B>,
-        f: impl Fn(CorsResource) -> TestResult<HeaderMap>,
-    ) -> TestResult {
-        let cfg = builder.into_config();
+    #[test]
+    fn origin_patterns_match_scheme_host_and_port() -> TestResult {
+        let cases = vec![
+            (
+                "https://*.corp.example:8000-8999",
+                "https://a.corp.example:8443",
+                true,
+            ),
+            (
+                "https://*.corp.example:8000-8999",
+                "https://a.b.corp.example:8000",
+                true,
+            ),
+            (
+                "https://*.corp.example:8000-8999",
+                "https://corp.example:8443",
+                false,
+            ),
+            (
+                "https://*.corp.example:8000-8999",
+                "https://a.corp.example",
+                false,
+            ),
+            (
+                "https://*.corp.example:8000-8999",
+                "http://a.corp.example:8443",
+                false,
+            ),
+            (
+                "https://*.corp.example:8000-8999",
+                "https://evilcorp.example:8443",
+                false,
+            ),
+            ("https://*.corp.example", "https://a.corp.example", true),
+            ("https://*.corp.example", "https://a.corp.example:443", true),
+            (
+                "https://*.corp.example",
+                "https://a.corp.example:8443",
+                false,
+            ),
+            (
+                "https://*.corp.example:*",
+                "https://a.corp.example:8443",
+                true,
+            ),
+            ("https://*:*", "https://anything.example:1", true),
+            ("https://*:*", "http://anything.example", false),
+            ("http://localhost:*", "http://localhost:3000", true),
+            ("http://localhost:*", "http://localhost", true),
+            ("http://localhost:*", "http://localhost.example", false),
+            ("*://example.com", "ws://example.com:80", true),
+            ("*://example.com", "https://example.com", true),
+            ("*://example.com", "https://example.com:80", false),
+            ("https://example.com:443", "https://example.com", true),
+            ("http://[::1]:*", "http://[::1]:8080", true),
+            // Ranges compare the effective port, so the implicit 443 counts
+            ("https://*.example:400-500", "https://a.example", true),
+            ("https://*.example:400-500", "https://a.example:443", true),
+            ("https://*.example:444-500", "https://a.example", false),
+            ("*://*.example:1-1000", "ws://a.example", true),
+            ("*://*.example:1-1000", "wss://a.example:8443", false),
+            (
+                "*://*.example:1-65535",
+                "chrome-extension://a.example",
+                false,
+            ),
+        ];
+
+        for (pattern, origin, expected) in cases {
+            let pattern = OriginPattern::parse(pattern)?;
+            assert_eq!(
+                pattern.matches(&Origin::parse(origin)?),
+                expected,
+                "{} / {}",
+                pattern,
+                origin
+            );
+        }
 
-        let mut headers = f(cfg.process_request(&req)?)?;
+        assert_eq!(
+            OriginPattern::parse("https://example.com:80-70"),
+            Err(InvalidOrigin::InvalidPort)
+        );
+        assert_eq!(
+            OriginPattern::parse("https://*.example/path"),
+            Err(InvalidOrigin::UnexpectedComponent)
+        );
 
-        let hdr = headers.remove(header::VARY).expect("vary header missing");
+        Ok(())
+    }
 
-        assert_set!(
-            hdr,
-            "origin",
-            "access-control-request-method",
-            "access-control-request-headers"
+    #[test]
+    fn patterns_allow_https_and_plain_http_localhost() -> TestResult {
+        let cfg = CorsBuilder::new()
+            .allow_origins(AllowedOrigins::try_from_patterns(&[
+                "https://*:*",
+                "http://localhost:*",
+            ])?)
+            .into_config();
+
+        let request = |origin: &'static str| {
+            http::Request::builder()
+                .header(header::ORIGIN, origin)
+                .body(())
+        };
+
+        assert_variant!(cfg.process_request(&request("https://a.example")?), Ok(_));
+        assert_variant!(
+            cfg.process_request(&request("http://localhost:8080")?),
+            Ok(_)
+        );
+        assert_variant!(
+            cfg.process_request(&request("http://a.example")?),
+            Err(InvalidRequest::DisallowedOrigin)
+        );
+        assert_variant!(
+            cfg.process_request(&request("null")?),
+            Err(InvalidRequest::DisallowedOrigin)
         );
 
         Ok(())
     }
 
-    fn common_test_allowed_credentials<B>(
-        builder: CorsBuilder,
-        req: http::Request<B>,
-        f: impl Fn(CorsResource) -> TestResult<HeaderMap>,
-    ) -> TestResult {
-        let cfg = builder.allow_credentials(true).into_config();
+    #[test]
+    fn indexed_origins_match_like_origins_and_patterns() -> TestResult {
+        let mut index = OriginIndex::new();
+        index.insert_origin("https://App.example:443")?;
+        index.insert_origin("null")?;
+        index.insert_pattern("https://*.corp.example:8000-8999")?;
+        index.insert_pattern("http://api.example:*")?;
+        index.insert_pattern("http://localhost:*")?;
+        assert_eq!(index.len(), 4);
+
+        let matches =
+            |origin: &str| -> TestResult<bool> { Ok(index.matches(&Origin::parse(origin)?)) };
+
+        assert!(matches("https://app.example")?);
+        assert!(!matches("http://app.example")?);
+        assert!(matches("https://a.b.corp.example:8443")?);
+        assert!(!matches("https://corp.example:8443")?);
+        assert!(!matches("https://a.corp.example")?);
+        assert!(!matches("https://acorp.example:8443")?);
+        assert!(matches("http://api.example:1234")?);
+        assert!(!matches("http://www.api.example")?);
+        assert!(matches("http://localhost:3000")?);
 
-        let mut headers = f(cfg.process_request(&req)?)?;
-        let hdr = headers
-            .remove(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
-            .expect("allow-credentials header missing");
+        let cfg = CorsBuilder::new()
+            .allow_origins(AllowedOrigins::try_indexed(
+                &["https://app.example", "null"],
+                &["https://*.corp.example"],
+            )?)
+            .into_config();
 
-        assert_eq!(hdr, "true");
+        let request = |origin: &'static str| {
+            http::Request::builder()
+                .header(header::ORIGIN, origin)
+                .body(())
+        };
+
+        assert_variant!(cfg.process_request(&request("https://app.example")?), Ok(_));
+        assert_variant!(
+            cfg.process_request(&request("https://x.corp.example")?),
+            Ok(_)
+        );
+        assert_variant!(cfg.process_request(&request("null")?), Ok(_));
+        assert_variant!(
+            cfg.process_request(&request("https://corp.example")?),
+            Err(InvalidRequest::DisallowedOrigin)
+        );
 
         Ok(())
     }
 
-    fn common_test_allowed_origin<B>(
-        builder: CorsBuilder,
-        req: http::Request<B>,
-        f: impl Fn(CorsResource) -> TestResult<HeaderMap>,
-    ) -> TestResult {
-        let cfg_no_wildcard_no_credentials = builder.clone().into_config();
-        let mut headers = f(cfg_no_wildcard_no_credentials.process_request(&req)?)?;
-        let hdr = headers
-            .remove(header::ACCESS_CONTROL_ALLOW_ORIGIN)
-            .expect("allow-origin header missing");
+    #[test]
+    fn isolation_relaxes_resource_policy_for_allowed_origins() -> TestResult {
+        let cors = CorsBuilder::new().allow_origins(AllowedOrigins::from_iter(vec![
+            HeaderValue::from_static("https://partner.example"),
+        ]));
+        let cfg = IsolationBuilder::cross_origin_isolated()
+            .allow_origins(cors.isolation().into_config().allowed_origins)
+            .into_config();
 
-        assert_eq!(hdr, "http://test.example");
+        let request = |origin: Option<&'static str>| {
+            let mut builder = http::Request::builder();
+            if let Some(origin) = origin {
+                builder = builder.header(header::ORIGIN, origin);
+            }
+            builder.body(())
+        };
 
-        let cfg_wildcard_no_credentials = builder.clone().prefer_wildcard(true).into_config();
-        let mut headers = f(cfg_wildcard_no_credentials.process_request(&req)?)?;
-        let hdr = headers
-            .remove(header::ACCESS_CONTROL_ALLOW_ORIGIN)
-            .expect("allow-origin header missing");
+        let headers = cfg.response_headers(&request(None)?);
+        assert_eq!(headers[CROSS_ORIGIN_OPENER_POLICY], "same-origin");
+        assert_eq!(headers[CROSS_ORIGIN_EMBEDDER_POLICY], "require-corp");
+        assert_eq!(headers[CROSS_ORIGIN_RESOURCE_POLICY], "same-origin");
+        assert_eq!(headers[header::VARY], "origin");
 
-        assert_eq!(hdr, "*");
+        let headers = cfg.response_headers(&request(Some("https://partner.example"))?);
+        assert_eq!(headers[CROSS_ORIGIN_RESOURCE_POLICY], "cross-origin");
 
-        let cfg_wildcard_credentials = builder
-            .clone()
-            .prefer_wildcard(true)
-            .allow_credentials(true)
+        let headers = cfg.response_headers(&request(Some("https://other.example"))?);
+        assert_eq!(headers[CROSS_ORIGIN_RESOURCE_POLICY], "same-origin");
+
+        // A disabled development preset shares no origin
+        let cfg = CorsBuilder::development()
+            .isolation()
+            .resource_policy(ResourcePolicy::SameSite)
             .into_config();
-        let mut headers = f(cfg_wildcard_credentials.process_request(&req)?)?;
-        let hdr = headers
-            .remove(header::ACCESS_CONTROL_ALLOW_ORIGIN)
-            .expect("allow-origin header missing");
+        let headers = cfg.response_headers(&request(Some("http://localhost:3000"))?);
+        assert_eq!(headers[CROSS_ORIGIN_RESOURCE_POLICY], "same-site");
 
-        assert_eq!(hdr, "http://test.example");
+        Ok(())
+    }
+
+    #[test]
+    fn isolation_report_only_policies() -> TestResult {
+        let cfg = IsolationBuilder::new()
+            .opener_policy_report_only(OpenerPolicy::SameOrigin)
+            .embedder_policy(EmbedderPolicy::Credentialless)
+            .embedder_policy_report_only(EmbedderPolicy::RequireCorp)
+            .report_to("isolation")
+            .into_config();
+
+        let headers = cfg.response_headers(&http::Request::new(()));
+        assert!(!headers.contains_key(CROSS_ORIGIN_OPENER_POLICY));
+        assert!(!headers.contains_key(CROSS_ORIGIN_RESOURCE_POLICY));
+        assert_eq!(
+            headers[CROSS_ORIGIN_OPENER_POLICY_REPORT_ONLY],
+            "same-origin; report-to=\"isolation\""
+        );
+        assert_eq!(
+            headers[CROSS_ORIGIN_EMBEDDER_POLICY],
+            "credentialless; report-to=\"isolation\""
+        );
+        assert_eq!(
+            headers[CROSS_ORIGIN_EMBEDDER_POLICY_REPORT_ONLY],
+            "require-corp; report-to=\"isolation\""
+        );
 
         Ok(())
     }
+
+    #[test]
+    fn resource_isolation_rejects_cross_site_requests() -> TestResult {
+        let builder = CorsBuilder::new()
+            .allow_origins(AllowedOrigins::from_iter(vec![HeaderValue::from_static(
+                "https://partner.example",
+            )]))
+            .allow_methods(vec![Method::GET, Method::POST]);
+        let cfg = builder
+            .clone()
+            .resource_isolation(ResourceIsolation::new().exempt_path("/embed/"))
+            .into_config();
+
+        let request =
+            |method: Method,
+             uri: &'static str,
+             origin: Option<&'static str>,
+             metadata: Option<(&'static str, &'static str, &'static str)>| {
+                let mut builder = http::Request::builder().method(method).uri(uri);
+                if let Some(origin) = origin {
+                    builder = builder.header(header::ORIGIN, origin);
+                }
+                if let Some((site, mode, dest)) = metadata {
+                    builder = builder
+                        .header(SEC_FETCH_SITE, site)
+                        .header(SEC_FETCH_MODE, mode)
+                        .header(SEC_FETCH_DEST, dest);
+                }
+                builder.body(())
+            };
+
+        // A cross-site image load and form post, which CORS never sees
+        let image = request(
+            Method::GET,
+            "/avatar.png",
+            None,
+            Some(("cross-site", "no-cors", "image")),
+        )?;
+        assert_variant!(
+            cfg.process_request(&image),
+            Err(InvalidRequest::CrossSiteRequest)
+        );
+        let form = request(
+            Method::POST,
+            "/transfer",
+            Some("https://evil.example"),
+            Some(("cross-site", "navigate", "document")),
+        )?;
+        assert_variant!(
+            cfg.process_request(&form),
+            Err(InvalidRequest::CrossSiteRequest)
+        );
+        assert_eq!(
+            InvalidRequest::CrossSiteRequest.reason(),
+            "cross_site_request"
+        );
+
+        // Allowed origins, navigations, same-site and exempt requests pass
+        let allowed = request(
+            Method::POST,
+            "/transfer",
+            Some("https://partner.example"),
+            Some(("cross-site", "cors", "empty")),
+        )?;
+        assert_variant!(cfg.process_request(&allowed), Ok(_));
+        let link = request(
+            Method::GET,
+            "/",
+            None,
+            Some(("cross-site", "navigate", "document")),
+        )?;
+        assert_variant!(cfg.process_request(&link), Ok(_));
+        let object = request(
+            Method::GET,
+            "/",
+            None,
+            Some(("cross-site", "navigate", "object")),
+        )?;
+        assert_variant!(
+            cfg.process_request(&object),
+            Err(InvalidRequest::CrossSiteRequest)
+        );
+        let same_site = request(
+            Method::POST,
+            "/transfer",
+            Some("https://www.site.example"),
+            Some(("same-site", "cors", "empty")),
+        )?;
+        assert_variant!(
+            cfg.process_request(&same_site),
+            Err(InvalidRequest::DisallowedOrigin)
+        );
+        let embed = request(
+            Method::GET,
+            "/embed/widget.js",
+            None,
+            Some(("cross-site", "no-cors", "script")),
+        )?;
+        assert_variant!(cfg.process_request(&embed), Ok(_));
+        let not_embed = request(
+            Method::GET,
+            "/embedded",
+            None,
+            Some(("cross-site", "no-cors", "script")),
+        )?;
+        assert_variant!(
+            cfg.process_request(&not_embed),
+            Err(InvalidRequest::CrossSiteRequest)
+        );
+
+        // Without metadata
+        let legacy = request(Method::POST, "/transfer", None, None)?;
+        assert_variant!(cfg.process_request(&legacy), Ok(_));
+        let cfg = builder
+            .resource_isolation(
+                ResourceIsolation::new()
+                    .allow_navigations(false)
+                    .missing_metadata(MissingMetadata::Deny),
+            )
+            .into_config();
+        assert_variant!(
+            cfg.process_request(&legacy),
+            Err(InvalidRequest::MissingFetchMetadata)
+        );
+        assert_variant!(
+            cfg.process_request(&link),
+            Err(InvalidRequest::CrossSiteRequest)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn csrf_protection_checks_source_of_unsafe_requests() -> TestResult {
+        let builder = CorsBuilder::new()
+            .allow_origins(AllowedOrigins::from_iter(vec![HeaderValue::from_static(
+                "https://partner.example",
+            )]))
+            .allow_methods(vec![Method::POST]);
+        let cfg = builder
+            .clone()
+            .csrf_protection(CsrfProtection::new())
+            .into_config();
+
+        let request = |method: Method, source: Option<(HeaderName, &'static str)>| {
+            let mut builder = http::Request::builder()
+                .method(method)
+                .uri("/transfer")
+                .header(header::HOST, "bank.example");
+            if let Some((name, value)) = source {
+                builder = builder.header(name, value);
+            }
+            builder.body(())
+        };
+
+        let forged = request(Method::POST, Some((header::ORIGIN, "https://evil.example")))?;
+        assert_variant!(
+            cfg.process_request(&forged),
+            Err(InvalidRequest::CsrfCheckFailed)
+        );
+        assert_eq!(
+            InvalidRequest::CsrfCheckFailed.reason(),
+            "csrf_check_failed"
+        );
+
+        let forged = request(
+            Method::DELETE,
+            Some((header::REFERER, "https://evil.example/page?q=1")),
+        )?;
+        assert_variant!(
+            cfg.process_request(&forged),
+            Err(InvalidRequest::CsrfCheckFailed)
+        );
+
+        let missing = request(Method::POST, None)?;
+        assert_variant!(
+            cfg.process_request(&missing),
+            Err(InvalidRequest::CsrfCheckFailed)
+        );
+
+        let allowed = request(
+            Method::POST,
+            Some((header::ORIGIN, "https://partner.example")),
+        )?;
+        assert_variant!(cfg.process_request(&allowed), Ok(_));
+
+        // The scheme of the server is unknown from `Host` alone, so the
+        // request may come from `http://bank.example`
+        let same_host = request(
+            Method::POST,
+            Some((header::REFERER, "https://bank.example/account")),
+        )?;
+        assert_variant!(
+            cfg.process_request(&same_host),
+            Err(InvalidRequest::CsrfCheckFailed)
+        );
+
+        // HTTP/2 requests carry the scheme in their URI
+        let same_origin = |source: (HeaderName, &'static str)| {
+            http::Request::builder()
+                .method(Method::POST)
+                .uri("https://bank.example/transfer")
+                .header(source.0, source.1)
+                .body(())
+        };
+        assert_variant!(
+            cfg.process_request(&same_origin((
+                header::REFERER,
+                "https://bank.example/account"
+            ))?),
+            Ok(_)
+        );
+        assert_variant!(
+            cfg.process_request(&same_origin((
+                header::REFERER,
+                "http://bank.example/account"
+            ))?),
+            Err(InvalidRequest::CsrfCheckFailed)
+        );
+        // A same-origin request is not subject to the CORS allowlist either
+        let headers = cfg
+            .process_request(&same_origin((header::ORIGIN, "https://bank.example"))?)?
+            .into_simple()?;
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        let https = builder
+            .clone()
+            .csrf_protection(CsrfProtection::new())
+            .same_origin(SameOrigin::new().scheme("https"))
+            .into_config();
+        assert_variant!(https.process_request(&same_host), Ok(_));
+        assert_variant!(
+            https.process_request(&request(
+                Method::POST,
+                Some((header::ORIGIN, "https://bank.example"))
+            )?),
+            Ok(_)
+        );
+        assert_variant!(
+            https.process_request(&request(
+                Method::POST,
+                Some((header::ORIGIN, "http://bank.example"))
+            )?),
+            Err(InvalidRequest::CsrfCheckFailed)
+        );
+
+        let other_port = request(
+            Method::POST,
+            Some((header::REFERER, "https://bank.example:8443/account")),
+        )?;
+        assert_variant!(
+            https.process_request(&other_port),
+            Err(InvalidRequest::CsrfCheckFailed)
+        );
+
+        // Safe methods are not checked
+        let safe = request(
+            Method::GET,
+            Some((header::REFERER, "https://evil.example/")),
+        )?;
+        assert_variant!(cfg.process_request(&safe), Ok(_));
+
+        let cfg = builder
+            .csrf_protection(CsrfProtection::new().allow_missing_source(true))
+            .into_config();
+        assert_variant!(cfg.process_request(&missing), Ok(_));
+
+        Ok(())
+    }
+
+    #[test]
+    fn same_origin_requests_bypass_cors() -> TestResult {
+        let builder = CorsBuilder::new()
+            .allow_origins(AllowedOrigins::from_iter(vec![HeaderValue::from_static(
+                "https://partner.example",
+            )]))
+            .allow_methods(vec![Method::POST]);
+
+        let request = |origin: &'static str, headers: &[(&'static str, &'static str)]| {
+            let mut builder = http::Request::builder()
+                .method(Method::POST)
+                .header(header::HOST, "app.example")
+                .header(header::ORIGIN, origin);
+            for (name, value) in headers {
+                builder = builder.header(*name, *value);
+            }
+            builder.body(())
+        };
+
+        // Without same-origin detection the allowlist applies
+        let same = request("https://app.example", &[])?;
+        assert_variant!(
+            builder.clone().into_config().process_request(&same),
+            Err(InvalidRequest::DisallowedOrigin)
+        );
+
+        // Nor while the scheme of the server is unknown
+        let cfg = builder.clone().same_origin(SameOrigin::new()).into_config();
+        assert_variant!(
+            cfg.process_request(&same),
+            Err(InvalidRequest::DisallowedOrigin)
+        );
+
+        let cfg = builder
+            .clone()
+            .same_origin(SameOrigin::new().scheme("https"))
+            .into_config();
+        let headers = cfg.process_request(&same)?.into_simple()?;
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert!(headers.contains_key(header::VARY));
+        for origin in &["https://app.example:8443", "http://app.example"] {
+            assert_variant!(
+                cfg.process_request(&request(origin, &[])?),
+                Err(InvalidRequest::DisallowedOrigin)
+            );
+        }
+
+        // Behind a proxy which terminates TLS
+        let cfg = builder
+            .clone()
+            .same_origin(SameOrigin::new().trust(ForwardedHeaders::XForwarded))
+            .into_config();
+        let proxied = request(
+            "https://www.app.example",
+            &[
+                ("x-forwarded-proto", "https"),
+                ("x-forwarded-host", "www.app.example"),
+            ],
+        )?;
+        assert_variant!(cfg.process_request(&proxied), Ok(_));
+        // Without a forwarded or configured scheme the request is cross-origin
+        let no_proto = request(
+            "https://www.app.example",
+            &[("x-forwarded-host", "www.app.example")],
+        )?;
+        assert_variant!(
+            cfg.process_request(&no_proto),
+            Err(InvalidRequest::DisallowedOrigin)
+        );
+        let downgraded = request("http://app.example", &[("x-forwarded-proto", "https")])?;
+        assert_variant!(
+            cfg.process_request(&downgraded),
+            Err(InvalidRequest::DisallowedOrigin)
+        );
+
+        // Untrusted forwarded headers are ignored
+        let spoofed = request(
+            "https://evil.example",
+            &[("forwarded", "proto=https;host=evil.example")],
+        )?;
+        assert_variant!(
+            cfg.process_request(&spoofed),
+            Err(InvalidRequest::DisallowedOrigin)
+        );
+
+        // The last element is the one set by the closest proxy
+        let cfg = builder
+            .clone()
+            .same_origin(SameOrigin::new().trust(ForwardedHeaders::Forwarded))
+            .into_config();
+        let forwarded = request(
+            "https://www.app.example",
+            &[(
+                "forwarded",
+                "host=evil.example, for=192.0.2.1;proto=https;host=\"www.app.example\"",
+            )],
+        )?;
+        assert_variant!(cfg.process_request(&forwarded), Ok(_));
+        let spoofed = request(
+            "https://evil.example",
+            &[(
+                "forwarded",
+                "host=evil.example, for=192.0.2.1;proto=https;host=\"www.app.example\"",
+            )],
+        )?;
+        assert_variant!(
+            cfg.process_request(&spoofed),
+            Err(InvalidRequest::DisallowedOrigin)
+        );
+
+        // The CSRF protection uses the same detection
+        let cfg = builder
+            .csrf_protection(CsrfProtection::new())
+            .same_origin(
+                SameOrigin::new()
+                    .scheme("https")
+                    .trust(ForwardedHeaders::XForwarded),
+            )
+            .into_config();
+        let form = |referer: &'static str| {
+            http::Request::builder()
+                .method(Method::POST)
+                .header(header::HOST, "10.0.0.1:8080")
+                .header("x-forwarded-host", "app.example")
+                .header(header::REFERER, referer)
+                .body(())
+        };
+        assert_variant!(cfg.process_request(&form("https://app.example/")?), Ok(_));
+        assert_variant!(
+            cfg.process_request(&form("http://app.example/")?),
+            Err(InvalidRequest::CsrfCheckFailed)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn websocket_handshakes_are_checked_against_their_origin_policy() -> TestResult {
+        let builder = CorsBuilder::new()
+            .allow_origins(AllowedOrigins::from_iter(vec![HeaderValue::from_static(
+                "https://partner.example",
+            )]))
+            .allow_methods(vec![Method::GET]);
+
+        let handshake = |origin: Option<&'static str>| {
+            let mut builder = http::Request::builder()
+                .uri("/ws")
+                .header(header::HOST, "app.example")
+                .header(header::CONNECTION, "keep-alive, Upgrade")
+                .header(header::UPGRADE, "websocket");
+            if let Some(origin) = origin {
+                builder = builder.header(header::ORIGIN, origin);
+            }
+            builder.body(())
+        };
+        assert!(is_websocket_upgrade(&handshake(None)?));
+        assert!(!is_websocket_upgrade(&http::Request::new(())));
+
+        let cfg = builder
+            .clone()
+            .websocket(WebSocketProtection::new())
+            .into_config();
+
+        let headers = cfg
+            .process_request(&handshake(Some("https://partner.example"))?)?
+            .into_simple()?;
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        // The scheme of the server is unknown from `Host` alone
+        assert_variant!(
+            cfg.process_request(&handshake(Some("https://app.example"))?),
+            Err(InvalidRequest::DisallowedWebSocketOrigin)
+        );
+
+        // Same-origin handshakes are allowed once the scheme is known
+        let same_origin = builder
+            .clone()
+            .websocket(WebSocketProtection::new())
+            .same_origin(SameOrigin::new().scheme("https"))
+            .into_config();
+        assert_variant!(
+            same_origin.process_request(&handshake(Some("https://app.example"))?),
+            Ok(_)
+        );
+        assert_variant!(
+            same_origin.process_request(&handshake(Some("http://app.example"))?),
+            Err(InvalidRequest::DisallowedWebSocketOrigin)
+        );
+        assert_variant!(
+            cfg.process_request(&handshake(Some("https://evil.example"))?),
+            Err(InvalidRequest::DisallowedWebSocketOrigin)
+        );
+        assert_variant!(
+            cfg.process_request(&handshake(None)?),
+            Err(InvalidRequest::DisallowedWebSocketOrigin)
+        );
+
+        // A separate allowlist
+        let cfg = builder
+            .websocket(
+                WebSocketProtection::new()
+                    .allow_origins(AllowedOrigins::from_iter(vec![HeaderValue::from_static(
+                        "https://chat.example",
+                    )]))
+                    .allow_missing_origin(true),
+            )
+            .into_config();
+        assert_variant!(
+            cfg.process_request(&handshake(Some("https://chat.example"))?),
+            Ok(_)
+        );
+        assert_variant!(
+            cfg.process_request(&handshake(Some("https://partner.example"))?),
+            Err(InvalidRequest::DisallowedWebSocketOrigin)
+        );
+        assert_variant!(cfg.process_request(&handshake(None)?), Ok(_));
+
+        Ok(())
+    }
+
+    #[test]
+    fn route_methods_match_like_axum() {
+        let routes = RouteMethods::new()
+            .route("/users", vec![Method::GET, Method::POST])
+            .route("/users/:id", vec![Method::GET])
+            .route("/users/:id", vec![Method::DELETE, Method::PATCH])
+            .route("/users/me", vec![Method::PUT])
+            .route("/files/*path", vec![Method::GET]);
+
+        let methods = |path: &str| {
+            routes
+                .find(path)
+                .map(|route| route.allow_methods_header().to_str().unwrap().to_string())
+        };
+
+        // As in axum, GET routes handle HEAD as well
+        assert_eq!(methods("/users").as_deref(), Some("GET,HEAD,POST"));
+        assert_eq!(
+            methods("/users/42").as_deref(),
+            Some("GET,HEAD,DELETE,PATCH")
+        );
+        assert_eq!(methods("/users/me").as_deref(), Some("PUT"));
+        assert_eq!(methods("/files/a/b.txt").as_deref(), Some("GET,HEAD"));
+        assert_eq!(methods("/files"), None);
+        assert_eq!(methods("/users/42/posts"), None);
+        assert_eq!(methods("/"), None);
+        assert_eq!(
+            routes
+                .find("/users")
+                .map(|route| route.allow_header().clone()),
+            Some(HeaderValue::from_static("GET,HEAD,POST,OPTIONS"))
+        );
+    }
+
+    #[test]
+    fn preflight_lists_the_methods_of_the_route() -> TestResult {
+        let cfg = CorsBuilder::new()
+            .allow_origins(AllowedOrigins::Any { allow_null: false })
+            .allow_methods(vec![Method::GET])
+            .route_methods(
+                RouteMethods::new()
+                    .route("/users/:id", vec![Method::GET, Method::DELETE])
+                    .route("/health", vec![Method::GET, Method::OPTIONS]),
+            )
+            .into_config();
+
+        let preflight = |uri: &'static str, method: &'static str| {
+            http::Request::builder()
+                .method(Method::OPTIONS)
+                .uri(uri)
+                .header(header::ORIGIN, "http://test.example")
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, method)
+                .body(())
+        };
+
+        let headers = cfg
+            .process_request(&preflight("/users/42", "DELETE")?)?
+            .into_preflight()?;
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_METHODS],
+            "GET,HEAD,DELETE"
+        );
+        assert_variant!(
+            cfg.process_request(&preflight("/users/42", "PUT")?),
+            Err(InvalidRequest::DisallowedMethod)
+        );
+
+        // Paths without a route fall back to allow_methods
+        let headers = cfg
+            .process_request(&preflight("/other", "GET")?)?
+            .into_preflight()?;
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET");
+        assert_variant!(
+            cfg.process_request(&preflight("/other", "DELETE")?),
+            Err(InvalidRequest::DisallowedMethod)
+        );
+
+        // Plain OPTIONS requests are answered with Allow
+        let options = |uri: &'static str| {
+            http::Request::builder()
+                .method(Method::OPTIONS)
+                .uri(uri)
+                .body(())
+        };
+        let resource = cfg.process_request(&options("/users/42")?)?;
+        assert!(resource.is_answered_locally());
+        assert_eq!(resource.headers()[header::ALLOW], "GET,HEAD,DELETE,OPTIONS");
+
+        // unless the route handles OPTIONS or is unknown
+        assert_variant!(
+            cfg.process_request(&options("/health")?),
+            Ok(CorsResource::Simple(_))
+        );
+        assert_variant!(
+            cfg.process_request(&options("/other")?),
+            Ok(CorsResource::Simple(_))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn development_preset_allows_loopback_only_when_enabled() -> TestResult {
+        let request = |origin: &'static str| {
+            http::Request::builder()
+                .header(header::ORIGIN, origin)
+                .body(())
+        };
+        let loopback = vec![
+            "http://localhost:5173",
+            "http://127.0.0.1:3000",
+            "http://127.1.2.3:8080",
+            "http://[::1]:4200",
+            "https://localhost",
+        ];
+
+        let enabled = CorsBuilder::development()
+            .enable_development(true)
+            .into_config();
+        for origin in &loopback {
+            assert_variant!(enabled.process_request(&request(origin)?), Ok(_));
+        }
+        for origin in &[
+            "http://example.com",
+            "http://localhost.example:3000",
+            "http://128.0.0.1",
+            "ws://localhost:3000",
+            "ftp://127.0.0.1",
+        ] {
+            assert_variant!(
+                enabled.process_request(&request(origin)?),
+                Err(InvalidRequest::DisallowedOrigin)
+            );
+        }
+
+        let disabled = CorsBuilder::development().into_config();
+        for origin in &loopback {
+            assert_variant!(
+                disabled.process_request(&request(origin)?),
+                Err(InvalidRequest::DisallowedOrigin)
+            );
+        }
+
+        // Development mode extends the origins of any builder
+        let production = || {
+            CorsBuilder::new().allow_origins(AllowedOrigins::from_iter(vec![
+                HeaderValue::from_static("https://app.example"),
+            ]))
+        };
+        let enabled = production().enable_development(true).into_config();
+        let disabled = production().enable_development(false).into_config();
+        for origin in &loopback {
+            assert_variant!(enabled.process_request(&request(origin)?), Ok(_));
+            assert_variant!(
+                disabled.process_request(&request(origin)?),
+                Err(InvalidRequest::DisallowedOrigin)
+            );
+        }
+        for cfg in &[enabled, disabled] {
+            assert_variant!(cfg.process_request(&request("https://app.example")?), Ok(_));
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tower")]
+    #[tokio::test]
+    async fn report_only_reports_divergences() -> TestResult {
+        use std::{
+            convert::Infallible,
+            sync::{Arc, Mutex},
+        };
+        use tower::{service_fn, Layer, ServiceExt};
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = reports.clone();
+
+        let candidate = CorsBuilder::new()
+            .allow_origins(AllowedOrigins::from_iter(vec![HeaderValue::from_static(
+                "http://foo.example",
+            )]))
+            .allow_methods(vec![Method::POST])
+            .into_config();
+
+        let service = CorsBuilder::new()
+            .allow_origins(AllowedOrigins::Any { allow_null: false })
+            .allow_methods(vec![Method::POST])
+            .report_only(candidate, move |d| {
+                sink.lock()
+                    .unwrap()
+                    .push((d.origin.cloned(), d.is_breaking()))
+            })
+            .into_layer()
+            .layer(service_fn(|_: http::Request<()>| async {
+                Ok::<_, Infallible>(http::Response::new(String::from("ok")))
+            }));
+
+        for origin in &["http://foo.example", "http://bar.example"] {
+            let req = http::Request::builder()
+                .header(header::ORIGIN, *origin)
+                .body(())?;
+            // The enforced decision applies: both requests are forwarded
+            let response = service.clone().oneshot(req).await?;
+            assert_eq!(response.status(), http::StatusCode::OK);
+        }
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(
+            *reports,
+            vec![(Some(HeaderValue::from_static("http://bar.example")), true)]
+        );
+
+        Ok(())
+    }
+
+    fn common_allows_when_origin_is_any(
+        cfg_builder: CorsBuilder,
+        req_builder: impl Fn() -> TestResult<http::request::Builder>,
+    ) -> TestResult {
+        let cfg = cfg_builder
+            .allow_origins(AllowedOrigins::Any { allow_null: false })
+            .into_config();
+
+        let req = req_builder()?
+            .header(
+                header::ORIGIN,
+                HeaderValue::from_static("http://test.example"),
+            )
+            .body(())?;
+
+        assert_variant!(cfg.process_request(&req), Ok(_));
+
+        Ok(())
+    }
+
+    fn common_disallows_null_origin_even_for_any(
+        cfg_builder: CorsBuilder,
+        req_builder: impl Fn() -> TestResult<http::request::Builder>,
+    ) -> TestResult {
+        let cfg = cfg_builder
+            .allow_origins(AllowedOrigins::Any { allow_null: false })
+            .into_config();
+
+        let req = req_builder()?
+            .header(header::ORIGIN, HeaderValue::from_static("null"))
+            .body(())?;
+
+        assert_variant!(cfg.process_request(&req), Err(_disallowed_origin));
+
+        Ok(())
+    }
+
+    fn common_allows_null_origin_for_any_when_configured(
+        cfg_builder: CorsBuilder,
+        req_builder: impl Fn() -> TestResult<http::request::Builder>,
+    ) -> TestResult {
+        let cfg = cfg_builder
+            .allow_origins(AllowedOrigins::Any { allow_null: true })
+            .into_config();
+
+        let req = req_builder()?
+            .header(header::ORIGIN, HeaderValue::from_static("null"))
+            .body(())?;
+
+        assert_variant!(cfg.process_request(&req), Ok(_));
+
+        Ok(())
+    }
+
+    fn common_compares_origin_against_allowed_origins(
+        cfg_builder: CorsBuilder,
+        req_builder: impl Fn() -> TestResult<http::request::Builder>,
+    ) -> TestResult {
+        let cfg = cfg_builder
+            .allow_origins(AllowedOrigins::from_iter(vec![
+                HeaderValue::from_static("http://foo.example"),
+                HeaderValue::from_static("http://bar.example"),
+            ]))
+            .into_config();
+
+        let allowed_req_foo = req_builder()?
+            .header(
+                header::ORIGIN,
+                HeaderValue::from_static("http://foo.example"),
+            )
+            .body(())?;
+
+        assert_variant!(cfg.process_request(&allowed_req_foo), Ok(_));
+
+        let allowed_req_bar = req_builder()?
+            .header(
+                header::ORIGIN,
+                HeaderValue::from_static("http://bar.example"),
+            )
+            .body(())?;
+
+        assert_variant!(cfg.process_request(&allowed_req_bar), Ok(_));
+
+        let disallowed_req_unlisted = req_builder()?
+            .header(
+                header::ORIGIN,
+                HeaderValue::from_static("http://quux.example"),
+            )
+            .body(())?;
+
+        assert_variant!(
+            cfg.process_request(&disallowed_req_unlisted),
+            Err(_disallowed_origin)
+        );
+
+        // Hosts are case-insensitive, origins are compared normalized
+        let allowed_req_differing_case = req_builder()?
+            .header(
+                header::ORIGIN,
+                HeaderValue::from_static("http://FOO.example"),
+            )
+            .body(())?;
+
+        assert_variant!(cfg.process_request(&allowed_req_differing_case), Ok(_));
+
+        let disallowed_req_differing_scheme = req_builder()?
+            .header(
+                header::ORIGIN,
+                HeaderValue::from_static("https://foo.example"),
+            )
+            .body(())?;
+
+        assert_variant!(
+            cfg.process_request(&disallowed_req_differing_scheme),
+            Err(_disallowed_origin)
+        );
+
+        Ok(())
+    }
+
+    fn common_test_vary_header<B>(
+        builder: CorsBuilder,
+        req: http::Request<B>,
+        f: impl Fn(CorsResource) -> TestResult<HeaderMap>,
+    ) -> TestResult {
+        let cfg = builder.into_config();
+
+        let mut headers = f(cfg.process_request(&req)?)?;
+
+        let hdr = headers.remove(header::VARY).expect("vary header missing");
+
+        assert_set!(
+            hdr,
+            "origin",
+            "access-control-request-method",
+            "access-control-request-headers"
+        );
+
+        Ok(())
+    }
+
+    fn common_test_allowed_credentials<B>(
+        builder: CorsBuilder,
+        req: http::Request<B>,
+        f: impl Fn(CorsResource) -> TestResult<HeaderMap>,
+    ) -> TestResult {
+        let cfg = builder.allow_credentials(true).into_config();
+
+        let mut headers = f(cfg.process_request(&req)?)?;
+        let hdr = headers
+            .remove(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+            .expect("allow-credentials header missing");
+
+        assert_eq!(hdr, "true");
+
+        Ok(())
+    }
+
+    fn common_test_allowed_origin<B>(
+        builder: CorsBuilder,
+        req: http::Request<B>,
+        f: impl Fn(CorsResource) -> TestResult<HeaderMap>,
+    ) -> TestResult {
+        let cfg_no_wildcard_no_credentials = builder.clone().into_config();
+        let mut headers = f(cfg_no_wildcard_no_credentials.process_request(&req)?)?;
+        let hdr = headers
+            .remove(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .expect("allow-origin header missing");
+
+        assert_eq!(hdr, "http://test.example");
+
+        let cfg_wildcard_no_credentials = builder.clone().prefer_wildcard(true).into_config();
+        let mut headers = f(cfg_wildcard_no_credentials.process_request(&req)?)?;
+        let hdr = headers
+            .remove(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .expect("allow-origin header missing");
+
+        assert_eq!(hdr, "*");
+
+        let cfg_wildcard_credentials = builder
+            .clone()
+            .prefer_wildcard(true)
+            .allow_credentials(true)
+            .into_config();
+        let mut headers = f(cfg_wildcard_credentials.process_request(&req)?)?;
+        let hdr = headers
+            .remove(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .expect("allow-origin header missing");
+
+        assert_eq!(hdr, "http://test.example");
+
+        Ok(())
+    }
+
+    /// The response headers as computed from the fields of the
+    /// configuration at request time
+    fn headers_at_request_time(cfg: &Config, kind: RequestKind, origin: &HeaderValue) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::VARY, cfg.vary_header.clone());
+        if kind == RequestKind::NoOrigin {
+            return headers;
+        }
+
+        let reflect = cfg.allow_credentials || !cfg.prefer_wildcard;
+        let allow_origin = if reflect {
+            origin.clone()
+        } else {
+            HeaderValue::from_static("*")
+        };
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        if cfg.allow_credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+
+        if kind == RequestKind::Preflight {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_METHODS,
+                cfg.allowed_methods_header.clone(),
+            );
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_HEADERS,
+                cfg.allowed_headers_header.clone(),
+            );
+            if let Some(ref max_age) = cfg.max_age {
+                headers.insert(header::ACCESS_CONTROL_MAX_AGE, max_age.clone());
+            }
+        } else {
+            if let Some(ref exposed) = cfg.exposed_headers_header {
+                headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, exposed.clone());
+            }
+            match cfg.timing_allow_origin {
+                Some(TimingAllowOrigin::Wildcard) => {
+                    headers.insert(TIMING_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+                }
+                Some(TimingAllowOrigin::Reflect) => {
+                    headers.insert(TIMING_ALLOW_ORIGIN, origin.clone());
+                }
+                None => {}
+            }
+        }
+        headers
+    }
+
+    #[test]
+    fn precomputed_headers_match_headers_computed_per_request() -> TestResult {
+        let origin = HeaderValue::from_static("http://test.example");
+        let requests = vec![
+            (
+                RequestKind::Preflight,
+                preflight_origin_request_builder()?
+                    .header(header::ORIGIN, origin.clone())
+                    .body(())?,
+            ),
+            (
+                RequestKind::Simple,
+                http::Request::builder()
+                    .method(Method::POST)
+                    .header(header::ORIGIN, origin.clone())
+                    .body(())?,
+            ),
+            (RequestKind::NoOrigin, http::Request::builder().body(())?),
+        ];
+
+        let timings = [
+            None,
+            Some(TimingAllowOrigin::Wildcard),
+            Some(TimingAllowOrigin::Reflect),
+        ];
+        for &credentials in &[false, true] {
+            for &wildcard in &[false, true] {
+                for &extras in &[false, true] {
+                    for timing in &timings {
+                        let mut builder = CorsBuilder::new()
+                            .allow_origins(AllowedOrigins::Any { allow_null: false })
+                            .allow_methods(vec![Method::GET, Method::POST])
+                            .allow_headers(vec![header::CONTENT_TYPE])
+                            .allow_credentials(credentials)
+                            .prefer_wildcard(wildcard);
+                        if extras {
+                            builder = builder
+                                .expose_headers(vec![HeaderName::from_static("x-custom")])
+                                .max_age(Duration::from_secs(60));
+                        }
+                        if let Some(timing) = *timing {
+                            builder = builder.timing_allow_origin(timing);
+                        }
+                        let cfg = builder.into_config();
+                        assert_eq!(
+                            cfg.allowed_methods().to_header_value(),
+                            cfg.allowed_methods_header
+                        );
+                        assert!(cfg.allowed_headers().contains(&header::CONTENT_TYPE));
+
+                        for (kind, request) in &requests {
+                            assert_eq!(
+                                cfg.process_request(request)?.into_headers(),
+                                headers_at_request_time(&cfg, *kind, &origin),
+                                "{:?} credentials:{} wildcard:{} extras:{} timing:{:?}",
+                                kind,
+                                credentials,
+                                wildcard,
+                                extras,
+                                timing
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tower")]
+    mod service {
+        use super::*;
+        use crate::body::*;
+        use std::{
+            alloc::{GlobalAlloc, Layout, System},
+            cell::Cell,
+        };
+
+        /// Counts the allocations of each thread, so that the counts of
+        /// tests running in parallel do not mix
+        struct CountingAllocator;
+
+        thread_local! {
+            static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+        }
+
+        unsafe impl GlobalAlloc for CountingAllocator {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+                System.alloc(layout)
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                System.dealloc(ptr, layout)
+            }
+        }
+
+        #[global_allocator]
+        static GLOBAL: CountingAllocator = CountingAllocator;
+
+        fn allocations_per_call<S>(service: &mut S) -> TestResult<usize>
+        where
+            S: tower::Service<http::Request<()>>,
+            S::Error: Into<TestError>,
+        {
+            use futures_util::FutureExt;
+            use tower::ServiceExt;
+
+            let request = || {
+                http::Request::builder()
+                    .method(Method::POST)
+                    .header(header::ORIGIN, "http://test.example")
+                    .body(())
+            };
+            let mut call = |request| -> TestResult<usize> {
+                service
+                    .ready()
+                    .now_or_never()
+                    .ok_or("inner service not ready")?
+                    .map_err(Into::into)?;
+                let before = ALLOCATIONS.with(Cell::get);
+                let response = service.call(request).now_or_never();
+                let after = ALLOCATIONS.with(Cell::get);
+                response.ok_or("response pending")?.map_err(Into::into)?;
+                Ok(after - before)
+            };
+
+            // The first request initializes lazily allocated state
+            call(request()?)?;
+            call(request()?)
+        }
+
+        #[test]
+        fn passthrough_body_allocates_less_than_boxing() -> TestResult {
+            use std::{convert::Infallible, future::ready};
+            use tower::{service_fn, Layer, ServiceExt};
+
+            let layer = CorsBuilder::new()
+                .allow_origins(AllowedOrigins::Any { allow_null: false })
+                .allow_methods(vec![Method::POST])
+                .into_layer();
+            let inner = |_: http::Request<()>| {
+                ready(Ok::<_, Infallible>(http::Response::new(String::from("ok"))))
+            };
+
+            let mut passthrough = layer.layer(service_fn(inner));
+            let mut boxed = layer
+                .layer(service_fn(inner))
+                .map_response(|response| response.map(axum::body::Body::new));
+
+            let (passthrough, boxed) = (
+                allocations_per_call(&mut passthrough)?,
+                allocations_per_call(&mut boxed)?,
+            );
+            assert!(
+                passthrough < boxed,
+                "passthrough={} boxed={}",
+                passthrough,
+                boxed
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn service_answers_preflight_and_forwards_simple() -> TestResult {
+            use axum::{body::Body, routing::post, Router};
+            use http::StatusCode;
+            use tower::ServiceExt;
+
+            let app = Router::new().route("/", post(|| async { "ok" })).layer(
+                CorsBuilder::new()
+                    .allow_origins(AllowedOrigins::Any { allow_null: false })
+                    .allow_methods(vec![Method::POST])
+                    .timing_allow_origin(TimingAllowOrigin::Reflect)
+                    .into_layer(),
+            );
+
+            let preflight = preflight_origin_request_builder()?
+                .uri("/")
+                .header(header::ORIGIN, "http://test.example")
+                .body(Body::empty())?;
+            let response = app.clone().oneshot(preflight).await?;
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+            assert_eq!(
+                response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+                "http://test.example"
+            );
+
+            let simple = http::Request::builder()
+                .method(Method::POST)
+                .uri("/")
+                .header(header::ORIGIN, "http://test.example")
+                .body(Body::empty())?;
+            let response = app.clone().oneshot(simple).await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+                "http://test.example"
+            );
+            assert_eq!(
+                response.headers()[TIMING_ALLOW_ORIGIN],
+                "http://test.example"
+            );
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+            assert_eq!(&body[..], b"ok");
+
+            let denied = http::Request::builder()
+                .method(Method::POST)
+                .uri("/")
+                .header(header::ORIGIN, "null")
+                .body(Body::empty())?;
+            let response = app.oneshot(denied).await?;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn service_rejects_websocket_hijacking() -> TestResult {
+            use axum::{body::Body, routing::get, Router};
+            use http::StatusCode;
+            use std::sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc,
+            };
+            use tower::ServiceExt;
+
+            let accepted = Arc::new(AtomicUsize::new(0));
+            let handler = {
+                let accepted = accepted.clone();
+                move || async move {
+                    accepted.fetch_add(1, Ordering::SeqCst);
+                    StatusCode::SWITCHING_PROTOCOLS
+                }
+            };
+            let app = Router::new().route("/ws", get(handler)).layer(
+                CorsBuilder::new()
+                    .allow_origins(AllowedOrigins::Any { allow_null: false })
+                    .websocket(
+                        WebSocketProtection::new().allow_origins(AllowedOrigins::from_iter(vec![
+                            HeaderValue::from_static("https://app.example"),
+                        ])),
+                    )
+                    .into_layer(),
+            );
+
+            let handshake = |origin: &'static str| {
+                http::Request::get("/ws")
+                    .header(header::CONNECTION, "upgrade")
+                    .header(header::UPGRADE, "websocket")
+                    .header(header::ORIGIN, origin)
+                    .body(Body::empty())
+            };
+
+            let response = app
+                .clone()
+                .oneshot(handshake("https://evil.example")?)
+                .await?;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            assert_eq!(accepted.load(Ordering::SeqCst), 0);
+
+            let response = app.oneshot(handshake("https://app.example")?).await?;
+            assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+            assert_eq!(accepted.load(Ordering::SeqCst), 1);
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn grpc_web_preset_serves_grpc_web_clients() -> TestResult {
+            use axum::{body::Body, response::IntoResponse, routing::post, Router};
+            use http::StatusCode;
+            use tower::ServiceExt;
+
+            // A gRPC-web frame: a flag byte, the big endian length and the
+            // payload. The flag 0x80 marks the trailers sent in the body.
+            fn frame(flag: u8, payload: &[u8]) -> Vec<u8> {
+                let mut frame = vec![flag];
+                frame.extend((payload.len() as u32).to_be_bytes());
+                frame.extend(payload);
+                frame
+            }
+
+            let trailers = frame(0x80, b"grpc-status:0\r\ngrpc-message:\r\n");
+            let mut body = frame(0x00, b"\x0a\x05hello");
+            body.extend(&trailers);
+            let response_body = body.clone();
+
+            let app = Router::new()
+                .route(
+                    "/echo.Echo/Say",
+                    post(|| async move {
+                        (
+                            [(header::CONTENT_TYPE, "application/grpc-web+proto")],
+                            response_body,
+                        )
+                            .into_response()
+                    }),
+                )
+                .layer(
+                    CorsBuilder::grpc_web()
+                        .allow_origins(AllowedOrigins::from_iter(vec![HeaderValue::from_static(
+                            "https://web.example",
+                        )]))
+                        .into_layer(),
+                );
+
+            let preflight = http::Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/echo.Echo/Say")
+                .header(header::ORIGIN, "https://web.example")
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                .header(
+                    header::ACCESS_CONTROL_REQUEST_HEADERS,
+                    "content-type,x-grpc-web,x-user-agent,grpc-timeout",
+                )
+                .body(Body::empty())?;
+            let response = app.clone().oneshot(preflight).await?;
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+            let call = http::Request::builder()
+                .method(Method::POST)
+                .uri("/echo.Echo/Say")
+                .header(header::ORIGIN, "https://web.example")
+                .header(header::CONTENT_TYPE, "application/grpc-web+proto")
+                .header("x-grpc-web", "1")
+                .header("grpc-timeout", "1S")
+                .body(Body::from(frame(0x00, b"\x0a\x05hello")))?;
+            let response = app.oneshot(call).await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+                "https://web.example"
+            );
+            let exposed = &response.headers()[header::ACCESS_CONTROL_EXPOSE_HEADERS];
+            assert_set!(
+                exposed,
+                "grpc-status",
+                "grpc-message",
+                "grpc-status-details-bin"
+            );
+
+            // The trailers in the body reach the client untouched
+            let received = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+            assert_eq!(&received[..], &body[..]);
+            assert!(received.ends_with(&trailers));
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn service_answers_options_with_route_methods() -> TestResult {
+            use axum::{body::Body, routing::get, Router};
+            use http::StatusCode;
+            use tower::ServiceExt;
+
+            let router = Router::new()
+                .route(
+                    "/items/:id",
+                    get(|| async { "item" }).put(|| async { "put" }),
+                )
+                .route("/files/*path", get(|| async { "file" }));
+            let routes =
+                RouteMethods::probe::<Body, _, _>(router.clone(), ["/items/:id", "/files/*path"])
+                    .await?;
+
+            // The probed methods are those axum itself allows
+            let delete = http::Request::builder()
+                .method(Method::DELETE)
+                .uri("/items/1")
+                .body(Body::empty())?;
+            let response = router.clone().oneshot(delete).await?;
+            assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+            let route = routes.find("/items/1").expect("route missing");
+            assert_eq!(
+                route.allow_methods_header(),
+                response.headers()[header::ALLOW]
+            );
+            assert_eq!(
+                routes
+                    .find("/files/a/b")
+                    .map(|route| route.allow_methods_header().clone()),
+                Some(HeaderValue::from_static("GET,HEAD"))
+            );
+
+            // Paths without a route are reported
+            assert_variant!(
+                RouteMethods::probe::<Body, _, _>(router.clone(), ["/missing"]).await,
+                Err(ProbeError::NoAllowHeader {
+                    status: StatusCode::NOT_FOUND,
+                    ..
+                })
+            );
+
+            let app = router.layer(
+                CorsBuilder::new()
+                    .allow_origins(AllowedOrigins::Any { allow_null: false })
+                    .route_methods(routes)
+                    .into_layer(),
+            );
+
+            let options = http::Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/items/1")
+                .body(Body::empty())?;
+            let response = app.clone().oneshot(options).await?;
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+            assert_eq!(response.headers()[header::ALLOW], "GET,HEAD,PUT,OPTIONS");
+
+            let preflight = http::Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/items/1")
+                .header(header::ORIGIN, "http://test.example")
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "PUT")
+                .body(Body::empty())?;
+            let response = app.oneshot(preflight).await?;
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+            assert_eq!(
+                response.headers()[header::ACCESS_CONTROL_ALLOW_METHODS],
+                "GET,HEAD,PUT"
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn service_passes_inner_body_through() -> TestResult {
+            use http_body::Body;
+            use std::convert::Infallible;
+            use tower::{service_fn, Layer, ServiceExt};
+
+            let service = CorsBuilder::new()
+                .allow_origins(AllowedOrigins::Any { allow_null: false })
+                .into_layer()
+                .layer(service_fn(|_: http::Request<()>| async {
+                    Ok::<_, Infallible>(http::Response::new(String::from("ok")))
+                }));
+
+            let req = http::Request::builder()
+                .header(header::ORIGIN, "http://test.example")
+                .body(())?;
+            let response = service.clone().oneshot(req).await?;
+            assert_variant!(response.body(), CorsBody::Inner { .. });
+            assert_eq!(response.body().size_hint().exact(), Some(2));
+
+            let req = http::Request::builder()
+                .header(header::ORIGIN, "null")
+                .body(())?;
+            let response = service.oneshot(req).await?;
+            assert_variant!(response.body(), CorsBody::Empty);
+            assert!(response.body().is_end_stream());
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn locally_answered_requests_do_not_consume_inner_readiness() -> TestResult {
+            use http::StatusCode;
+            use tokio_test::{assert_pending, assert_ready_ok};
+            use tower_test::mock;
+
+            let layer = CorsLayer::new(std::sync::Arc::new(
+                CorsBuilder::new()
+                    .allow_origins(AllowedOrigins::from_iter(vec![HeaderValue::from_static(
+                        "http://test.example",
+                    )]))
+                    .allow_methods(vec![Method::POST])
+                    .into_config(),
+            ));
+            let (mut service, mut handle) =
+                mock::spawn_layer::<http::Request<()>, http::Response<String>, _>(layer);
+
+            // The backpressure of the inner service is passed through
+            handle.allow(0);
+            assert_pending!(service.poll_ready());
+
+            // A single request may reach the inner service
+            handle.allow(1);
+            assert_ready_ok!(service.poll_ready());
+
+            let preflight = preflight_origin_request_builder()?
+                .header(header::ORIGIN, "http://test.example")
+                .body(())?;
+            let response = service.call(preflight).await.map_err(|e| e as TestError)?;
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+            assert_ready_ok!(service.poll_ready());
+            let rejected = http::Request::builder()
+                .header(header::ORIGIN, "http://other.example")
+                .body(())?;
+            let response = service.call(rejected).await.map_err(|e| e as TestError)?;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+            // The readiness polled before the preflight is still available
+            assert_ready_ok!(service.poll_ready());
+            let simple = http::Request::builder()
+                .header(header::ORIGIN, "http://test.example")
+                .body(())?;
+            let response = service.call(simple);
+            let (_, send) = handle.next_request().await.expect("request forwarded");
+            send.send_response(http::Response::new(String::from("ok")));
+            let response = response.await.map_err(|e| e as TestError)?;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            // and has now been consumed
+            assert_pending!(service.poll_ready());
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn inner_service_need_not_be_clone() -> TestResult {
+            use std::{
+                convert::Infallible,
+                future::{ready, Ready},
+                task::{Context, Poll},
+            };
+            use tower::{Service, ServiceExt};
+
+            struct NotClone;
+
+            impl Service<http::Request<()>> for NotClone {
+                type Response = http::Response<String>;
+                type Error = Infallible;
+                type Future = Ready<Result<Self::Response, Self::Error>>;
+
+                fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+                    Poll::Ready(Ok(()))
+                }
+
+                fn call(&mut self, _: http::Request<()>) -> Self::Future {
+                    ready(Ok(http::Response::new(String::from("ok"))))
+                }
+            }
+
+            let service = CorsService::new(
+                NotClone,
+                std::sync::Arc::new(CorsBuilder::new().into_config()),
+            );
+            let response = service.oneshot(http::Request::new(())).await?;
+            assert_eq!(response.status(), http::StatusCode::OK);
+
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "tower")]
+    mod isolation_service {
+        use super::*;
+        use axum::{body::Body, response::IntoResponse, routing::get, Router};
+        use tower::ServiceExt;
+
+        #[tokio::test]
+        async fn isolation_layer_sets_headers_per_route() -> TestResult {
+            let app = Router::new()
+                .route("/", get(|| async { "ok" }))
+                .route(
+                    "/public",
+                    get(|| async {
+                        ([(CROSS_ORIGIN_RESOURCE_POLICY, "cross-origin")], "ok").into_response()
+                    }),
+                )
+                .layer(IsolationBuilder::cross_origin_isolated().into_layer());
+
+            let request = |uri: &'static str| http::Request::get(uri).body(Body::empty());
+
+            let response = app.clone().oneshot(request("/")?).await?;
+            assert_eq!(
+                response.headers()[CROSS_ORIGIN_RESOURCE_POLICY],
+                "same-origin"
+            );
+            assert_eq!(
+                response.headers()[CROSS_ORIGIN_OPENER_POLICY],
+                "same-origin"
+            );
+            assert_eq!(
+                response.headers()[CROSS_ORIGIN_EMBEDDER_POLICY],
+                "require-corp"
+            );
+
+            // Headers set by the route take precedence
+            let response = app.oneshot(request("/public")?).await?;
+            assert_eq!(
+                response.headers()[CROSS_ORIGIN_RESOURCE_POLICY],
+                "cross-origin"
+            );
+            assert_eq!(
+                response.headers()[CROSS_ORIGIN_OPENER_POLICY],
+                "same-origin"
+            );
+
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "tower")]
+    mod simulator {
+        use super::*;
+        use crate::simulator::*;
+        use axum::{
+            body::Body,
+            response::IntoResponse,
+            routing::{get, put},
+            Router,
+        };
+
+        fn browser_for(cors: CorsBuilder) -> Browser<Router> {
+            let app = Router::new()
+                .route(
+                    "/",
+                    get(|| async {
+                        (
+                            [
+                                (header::CONTENT_TYPE, "text/plain"),
+                                (header::SET_COOKIE, "session=1"),
+                                (HeaderName::from_static("x-request-id"), "1"),
+                                (HeaderName::from_static("x-internal"), "1"),
+                            ],
+                            "ok",
+                        )
+                            .into_response()
+                    }),
+                )
+                .route("/", put(|| async { "ok" }))
+                .layer(cors.into_layer());
+            Browser::new(app)
+        }
+
+        fn allowlist() -> CorsBuilder {
+            CorsBuilder::new()
+                .allow_origins(AllowedOrigins::Origins(BTreeSet::from_iter(vec![
+                    HeaderValue::from_static("https://app.example"),
+                ])))
+                .allow_methods(vec![Method::GET, Method::PUT])
+                .allow_headers(vec![header::AUTHORIZATION])
+                .expose_headers(vec![HeaderName::from_static("x-request-id")])
+                .allow_credentials(true)
+        }
+
+        fn fetch(origin: &str, method: Method) -> TestResult<Fetch> {
+            Ok(Fetch::new(origin, method, "https://api.example/")?)
+        }
+
+        #[tokio::test]
+        async fn simulator_preflights_unsafe_requests_only() -> TestResult {
+            let mut browser = browser_for(allowlist());
+
+            let simple = fetch("https://app.example", Method::GET)?
+                .header(header::ACCEPT, HeaderValue::from_static("text/plain"))
+                .header(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("text/plain; charset=utf-8"),
+                );
+            assert!(!simple.needs_preflight());
+            let verdict = browser.fetch::<Body, _>(&simple).await;
+            assert!(verdict.is_allowed());
+            assert!(!verdict.preflighted);
+
+            for unsafe_fetch in [
+                fetch("https://app.example", Method::PUT)?,
+                fetch("https://app.example", Method::GET)?.header(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/json"),
+                ),
+                fetch("https://app.example", Method::GET)?
+                    .header(header::AUTHORIZATION, HeaderValue::from_static("Bearer t")),
+            ] {
+                let verdict = browser.fetch::<Body, _>(&unsafe_fetch).await;
+                assert!(verdict.preflighted, "{:?}", unsafe_fetch);
+            }
+
+            // Forbidden headers cannot be set by the page
+            let forged = fetch("https://app.example", Method::GET)?.header(
+                header::ORIGIN,
+                HeaderValue::from_static("https://evil.example"),
+            );
+            assert!(!forged.needs_preflight());
+            assert!(browser.fetch::<Body, _>(&forged).await.is_allowed());
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn simulator_reports_why_a_fetch_is_blocked() -> TestResult {
+            let mut browser = browser_for(allowlist());
+
+            let verdict = browser
+                .fetch::<Body, _>(&fetch("https://evil.example", Method::PUT)?)
+                .await;
+            assert_eq!(
+                verdict.blocked(),
+                Some(&Blocked {
+                    stage: Stage::Preflight,
+                    reason: BlockReason::PreflightStatus(http::StatusCode::FORBIDDEN),
+                })
+            );
+
+            // A server which answers every preflight, but lists a method in
+            // lowercase and allows any header
+            let mut browser = Browser::new(tower::service_fn(|_: http::Request<Body>| async {
+                http::Response::builder()
+                    .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "https://app.example")
+                    .header(header::ACCESS_CONTROL_ALLOW_METHODS, "GET, put")
+                    .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "*")
+                    .body(Body::empty())
+            }));
+
+            let verdict = browser
+                .fetch::<Body, _>(&fetch("https://app.example", Method::from_bytes(b"put")?)?)
+                .await;
+            assert_eq!(
+                verdict.blocked().map(|b| &b.reason),
+                Some(&BlockReason::MethodNotAllowed(Method::PUT))
+            );
+
+            // The wildcard does not cover `Authorization`
+            let verdict = browser
+                .fetch::<Body, _>(
+                    &fetch("https://app.example", Method::GET)?
+                        .header(
+                            HeaderName::from_static("x-custom"),
+                            HeaderValue::from_static("1"),
+                        )
+                        .header(header::AUTHORIZATION, HeaderValue::from_static("Bearer t")),
+                )
+                .await;
+            assert_eq!(
+                verdict.blocked().map(|b| &b.reason),
+                Some(&BlockReason::HeaderNotAllowed(header::AUTHORIZATION))
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn simulator_applies_credentials_mode() -> TestResult {
+            let any = || {
+                CorsBuilder::new()
+                    .allow_origins(AllowedOrigins::Any { allow_null: false })
+                    .allow_methods(vec![Method::GET])
+                    .prefer_wildcard(true)
+            };
+
+            let mut browser = browser_for(any());
+            let omit = fetch("https://app.example", Method::GET)?;
+            assert!(browser.fetch::<Body, _>(&omit).await.is_allowed());
+
+            // The wildcard is not accepted for requests with credentials
+            let include = omit.credentials(CredentialsMode::Include);
+            let verdict = browser.fetch::<Body, _>(&include).await;
+            assert_eq!(
+                verdict.blocked().map(|b| &b.reason),
+                Some(&BlockReason::WildcardOrigin)
+            );
+
+            // Credentials require the origin and `Access-Control-Allow-Credentials`
+            let mut browser = browser_for(any().allow_credentials(true));
+            assert!(browser.fetch::<Body, _>(&include).await.is_allowed());
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn simulator_exposes_safelisted_and_exposed_headers() -> TestResult {
+            let mut browser = browser_for(allowlist());
+
+            let verdict = browser
+                .fetch::<Body, _>(&fetch("https://app.example", Method::GET)?)
+                .await;
+            let exposed = verdict.exposed().ok_or("blocked")?;
+            assert_eq!(exposed.status, http::StatusCode::OK);
+            assert!(exposed.headers.contains_key(header::CONTENT_TYPE));
+            assert!(exposed.headers.contains_key("x-request-id"));
+            assert!(!exposed.headers.contains_key("x-internal"));
+            assert!(!exposed.headers.contains_key(header::SET_COOKIE));
+
+            // Same-origin responses are not filtered, except for cookies
+            let verdict = browser
+                .fetch::<Body, _>(&fetch("https://api.example", Method::GET)?)
+                .await;
+            let exposed = verdict.exposed().ok_or("blocked")?;
+            assert!(exposed.headers.contains_key("x-internal"));
+            assert!(!exposed.headers.contains_key(header::SET_COOKIE));
+
+            Ok(())
+        }
+
+        #[test]
+        fn simulator_validates_the_url_up_front() -> TestResult {
+            for url in &["/relative", "https://", "https://api.example/a b"] {
+                assert_variant!(
+                    Fetch::new("https://app.example", Method::GET, url),
+                    Err(InvalidOrigin::Syntax)
+                );
+            }
+            // The fragment is not part of the request
+            let fetch = Fetch::new(
+                "https://app.example",
+                Method::GET,
+                "https://api.example/page#section",
+            )?;
+            assert!(fetch.is_cross_origin());
+
+            Ok(())
+        }
+    }
+
+    /// A conformance suite for `CorsService`, derived from the CORS
+    /// protocol of the Fetch standard and the CORS cases of the
+    /// web-platform-tests: https://fetch.spec.whatwg.org/#http-cors-protocol
+    /// and https://github.com/web-platform-tests/wpt/tree/master/fetch/api/cors
+    ///
+    /// `BROWSER_CASES` are fetches as a browser sends them, checked with the
+    /// `Browser` simulator. `RAW_CASES` are requests a browser never sends
+    /// but proxies and other clients do, checked on the response.
+    ///
+    /// Intentional deviations, each covered by a case below:
+    /// - Requests from a disallowed origin and preflights for a disallowed
+    ///   method or header are answered with 403 instead of being answered
+    ///   without CORS headers. The browser blocks both alike.
+    /// - Request origins are normalized before matching the allowlist:
+    ///   scheme and host are compared case-insensitively and the default
+    ///   port is elided. `Access-Control-Allow-Origin` echoes the request
+    ///   origin byte for byte, so the CORS check of the browser still passes.
+    /// - A request with several `Origin` lines is rejected, rather than
+    ///   matching the first line.
+    #[cfg(feature = "tower")]
+    mod conformance {
+        use super::*;
+        use crate::simulator::*;
+        use axum::{body::Body, routing::any, Router};
+        use http::StatusCode;
+        use tower::ServiceExt;
+
+        const APP: &str = "https://app.example";
+        const URL: &str = "https://api.example/";
+
+        enum Expect {
+            Allowed { preflighted: bool },
+            Blocked(Stage),
+        }
+
+        struct BrowserCase {
+            name: &'static str,
+            cors: fn() -> CorsBuilder,
+            fetch: fn() -> TestResult<Fetch>,
+            expect: Expect,
+        }
+
+        struct RawCase {
+            name: &'static str,
+            cors: fn() -> CorsBuilder,
+            request: fn() -> http::request::Builder,
+            status: StatusCode,
+            /// The expected `Access-Control-Allow-Origin`, if any
+            allow_origin: Option<&'static str>,
+        }
+
+        fn app(cors: CorsBuilder) -> Router {
+            Router::new()
+                .route("/", any(|| async { "ok" }))
+                .layer(cors.into_layer())
+        }
+
+        fn any_origin() -> CorsBuilder {
+            CorsBuilder::new()
+                .allow_origins(AllowedOrigins::Any { allow_null: false })
+                .allow_methods(vec![Method::GET, Method::POST])
+                .prefer_wildcard(true)
+        }
+
+        fn allowlist() -> CorsBuilder {
+            CorsBuilder::new()
+                .allow_origins(AllowedOrigins::Origins(BTreeSet::from_iter(vec![
+                    HeaderValue::from_static(APP),
+                ])))
+                .allow_methods(vec![Method::GET, Method::POST, Method::PATCH])
+                .allow_headers(vec![
+                    header::CONTENT_TYPE,
+                    HeaderName::from_static("x-a"),
+                    HeaderName::from_static("x-b"),
+                ])
+        }
+
+        fn fetch(method: &str) -> TestResult<Fetch> {
+            Ok(Fetch::new(
+                APP,
+                Method::from_bytes(method.as_bytes())?,
+                URL,
+            )?)
+        }
+
+        fn preflight(method: &str) -> http::request::Builder {
+            http::Request::builder()
+                .method(Method::OPTIONS)
+                .uri(URL)
+                .header(header::ORIGIN, APP)
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, method)
+        }
+
+        const BROWSER_CASES: &[BrowserCase] = &[
+            BrowserCase {
+                name: "a wildcard is accepted without credentials",
+                cors: any_origin,
+                fetch: || fetch("GET"),
+                expect: Expect::Allowed { preflighted: false },
+            },
+            BrowserCase {
+                name: "with credentials, the origin is sent instead of the wildcard",
+                cors: || any_origin().allow_credentials(true),
+                fetch: || Ok(fetch("GET")?.credentials(CredentialsMode::Include)),
+                expect: Expect::Allowed { preflighted: false },
+            },
+            BrowserCase {
+                name: "credentials require Access-Control-Allow-Credentials",
+                cors: allowlist,
+                fetch: || Ok(fetch("GET")?.credentials(CredentialsMode::Include)),
+                expect: Expect::Blocked(Stage::Request),
+            },
+            BrowserCase {
+                name: "a preflight with credentials",
+                cors: || allowlist().allow_credentials(true),
+                fetch: || Ok(fetch("PATCH")?.credentials(CredentialsMode::Include)),
+                expect: Expect::Allowed { preflighted: true },
+            },
+            BrowserCase {
+                name: "the null origin is not matched by any origin by default",
+                cors: any_origin,
+                fetch: || Ok(Fetch::new("null", Method::GET, URL)?),
+                expect: Expect::Blocked(Stage::Request),
+            },
+            BrowserCase {
+                name: "the null origin when it is allowed",
+                cors: || CorsBuilder::new().allow_origins(AllowedOrigins::Any { allow_null: true }),
+                fetch: || Ok(Fetch::new("null", Method::GET, URL)?),
+                expect: Expect::Allowed { preflighted: false },
+            },
+            BrowserCase {
+                name: "the null origin in an allowlist",
+                cors: || {
+                    CorsBuilder::new().allow_origins(AllowedOrigins::Origins(BTreeSet::from_iter(
+                        vec![HeaderValue::from_static("null")],
+                    )))
+                },
+                fetch: || Ok(Fetch::new("null", Method::GET, URL)?),
+                expect: Expect::Allowed { preflighted: false },
+            },
+            BrowserCase {
+                name: "a non-simple method is preflighted",
+                cors: allowlist,
+                fetch: || fetch("PATCH"),
+                expect: Expect::Allowed { preflighted: true },
+            },
+            BrowserCase {
+                name: "a non-simple method which is not allowed",
+                cors: allowlist,
+                fetch: || fetch("DELETE"),
+                expect: Expect::Blocked(Stage::Preflight),
+            },
+            BrowserCase {
+                name: "standard methods are uppercased by the browser",
+                cors: || allowlist().allow_methods(vec![Method::DELETE]),
+                fetch: || fetch("delete"),
+                expect: Expect::Allowed { preflighted: true },
+            },
+            BrowserCase {
+                name: "other methods are case-sensitive",
+                cors: allowlist,
+                fetch: || fetch("patch"),
+                expect: Expect::Blocked(Stage::Preflight),
+            },
+            BrowserCase {
+                name: "a safelisted content type is not preflighted",
+                cors: allowlist,
+                fetch: || {
+                    Ok(fetch("POST")?
+                        .header(header::CONTENT_TYPE, HeaderValue::from_static("text/plain")))
+                },
+                expect: Expect::Allowed { preflighted: false },
+            },
+            BrowserCase {
+                name: "another content type is preflighted",
+                cors: allowlist,
+                fetch: || {
+                    Ok(fetch("POST")?.header(
+                        header::CONTENT_TYPE,
+                        HeaderValue::from_static("application/json"),
+                    ))
+                },
+                expect: Expect::Allowed { preflighted: true },
+            },
+            BrowserCase {
+                name: "a header which is not allowed",
+                cors: allowlist,
+                fetch: || {
+                    Ok(fetch("GET")?.header(
+                        HeaderName::from_static("x-c"),
+                        HeaderValue::from_static("1"),
+                    ))
+                },
+                expect: Expect::Blocked(Stage::Preflight),
+            },
+        ];
+
+        const RAW_CASES: &[RawCase] = &[
+            RawCase {
+                name: "requested headers with whitespace around the names",
+                cors: allowlist,
+                request: || {
+                    preflight("POST").header(header::ACCESS_CONTROL_REQUEST_HEADERS, "x-a, x-b")
+                },
+                status: StatusCode::NO_CONTENT,
+                allow_origin: Some(APP),
+            },
+            RawCase {
+                name: "requested headers over several lines",
+                cors: allowlist,
+                request: || {
+                    preflight("POST")
+                        .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "x-a")
+                        .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "x-b")
+                },
+                status: StatusCode::NO_CONTENT,
+                allow_origin: Some(APP),
+            },
+            RawCase {
+                name: "requested header names are case-insensitive",
+                cors: allowlist,
+                request: || preflight("POST").header(header::ACCESS_CONTROL_REQUEST_HEADERS, "X-A"),
+                status: StatusCode::NO_CONTENT,
+                allow_origin: Some(APP),
+            },
+            RawCase {
+                name: "an empty list of requested headers",
+                cors: allowlist,
+                request: || preflight("POST").header(header::ACCESS_CONTROL_REQUEST_HEADERS, ""),
+                status: StatusCode::NO_CONTENT,
+                allow_origin: Some(APP),
+            },
+            RawCase {
+                name: "a requested header which is not allowed, among allowed ones",
+                cors: allowlist,
+                request: || {
+                    preflight("POST").header(header::ACCESS_CONTROL_REQUEST_HEADERS, "x-a,x-c")
+                },
+                status: StatusCode::FORBIDDEN,
+                allow_origin: None,
+            },
+            RawCase {
+                name: "the requested method is case-sensitive",
+                cors: allowlist,
+                request: || preflight("patch"),
+                status: StatusCode::FORBIDDEN,
+                allow_origin: None,
+            },
+            RawCase {
+                name: "OPTIONS without a requested method is not a preflight",
+                cors: allowlist,
+                request: || {
+                    http::Request::builder()
+                        .method(Method::OPTIONS)
+                        .uri(URL)
+                        .header(header::ORIGIN, APP)
+                },
+                status: StatusCode::OK,
+                allow_origin: Some(APP),
+            },
+            // Deviation: rejected instead of forwarded without CORS headers
+            RawCase {
+                name: "a request from a disallowed origin",
+                cors: allowlist,
+                request: || http::Request::get(URL).header(header::ORIGIN, "https://evil.example"),
+                status: StatusCode::FORBIDDEN,
+                allow_origin: None,
+            },
+            // Deviation: origins are normalized before matching, and echoed
+            RawCase {
+                name: "an origin in uppercase",
+                cors: allowlist,
+                request: || http::Request::get(URL).header(header::ORIGIN, "HTTPS://APP.example"),
+                status: StatusCode::OK,
+                allow_origin: Some("HTTPS://APP.example"),
+            },
+            RawCase {
+                name: "an origin with the default port",
+                cors: allowlist,
+                request: || {
+                    http::Request::get(URL).header(header::ORIGIN, "https://app.example:443")
+                },
+                status: StatusCode::OK,
+                allow_origin: Some("https://app.example:443"),
+            },
+            // Deviation: rejected instead of matching the first line
+            RawCase {
+                name: "several origin lines",
+                cors: allowlist,
+                request: || {
+                    http::Request::get(URL)
+                        .header(header::ORIGIN, APP)
+                        .header(header::ORIGIN, "https://evil.example")
+                },
+                status: StatusCode::FORBIDDEN,
+                allow_origin: None,
+            },
+        ];
+
+        #[tokio::test]
+        async fn conforms_to_the_browser_side_of_fetch() -> TestResult {
+            for case in BROWSER_CASES {
+                let mut browser = Browser::new(app((case.cors)()));
+                let verdict = browser.fetch::<Body, _>(&(case.fetch)()?).await;
+                match case.expect {
+                    Expect::Allowed { preflighted } => {
+                        assert!(verdict.is_allowed(), "{}: {:?}", case.name, verdict);
+                        assert_eq!(verdict.preflighted, preflighted, "{}", case.name);
+                    }
+                    Expect::Blocked(stage) => {
+                        let blocked = verdict.blocked().ok_or(case.name)?;
+                        assert_eq!(blocked.stage, stage, "{}: {:?}", case.name, blocked);
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn conforms_on_requests_browsers_do_not_send() -> TestResult {
+            for case in RAW_CASES {
+                let request = (case.request)().body(Body::empty())?;
+                let response = app((case.cors)()).oneshot(request).await?;
+                assert_eq!(response.status(), case.status, "{}", case.name);
+                assert_eq!(
+                    response
+                        .headers()
+                        .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                        .map(HeaderValue::to_str)
+                        .transpose()?,
+                    case.allow_origin,
+                    "{}",
+                    case.name
+                );
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    mod trace {
+        use super::*;
+        use std::{
+            fmt,
+            sync::{Arc, Mutex},
+        };
+        use tracing::{
+            field::{Field, Visit},
+            span, Event, Metadata, Subscriber,
+        };
+
+        /// Records the fields of every span and event
+        #[derive(Clone, Default)]
+        struct Recorder {
+            spans: Arc<Mutex<Vec<Fields>>>,
+            events: Arc<Mutex<Vec<Fields>>>,
+        }
+
+        type Fields = Vec<(String, String)>;
+
+        struct Visitor<'a>(&'a mut Fields);
+
+        impl Visit for Visitor<'_> {
+            fn record_str(&mut self, field: &Field, value: &str) {
+                self.0.push((field.name().to_string(), value.to_string()));
+            }
+
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                self.0
+                    .push((field.name().to_string(), format!("{:?}", value)));
+            }
+        }
+
+        impl Recorder {
+            fn span_field(&self, name: &str) -> Option<String> {
+                let spans = self.spans.lock().unwrap();
+                let span = spans.last()?;
+                span.iter()
+                    .rev()
+                    .find(|(field, _)| field == name)
+                    .map(|(_, value)| value.clone())
+            }
+        }
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, attributes: &span::Attributes<'_>) -> span::Id {
+                let mut spans = self.spans.lock().unwrap();
+                let mut fields = Vec::new();
+                attributes.record(&mut Visitor(&mut fields));
+                spans.push(fields);
+                span::Id::from_u64(spans.len() as u64)
+            }
+
+            fn record(&self, id: &span::Id, values: &span::Record<'_>) {
+                let mut spans = self.spans.lock().unwrap();
+                let span = &mut spans[id.into_u64() as usize - 1];
+                values.record(&mut Visitor(span));
+            }
+
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                let mut fields = Vec::new();
+                event.record(&mut Visitor(&mut fields));
+                self.events.lock().unwrap().push(fields);
+            }
+
+            fn enter(&self, _: &span::Id) {}
+
+            fn exit(&self, _: &span::Id) {}
+        }
+
+        #[test]
+        fn records_the_decision_of_each_request() -> TestResult {
+            let cfg = CorsBuilder::new()
+                .allow_origins(AllowedOrigins::from_iter(vec![HeaderValue::from_static(
+                    "http://test.example",
+                )]))
+                .allow_methods(vec![Method::POST])
+                .into_config();
+            let recorder = Recorder::default();
+
+            let preflight = preflight_origin_request_builder()?
+                .header(header::ORIGIN, "http://test.example")
+                .body(())?;
+            tracing::subscriber::with_default(recorder.clone(), || {
+                cfg.process_request(&preflight)
+            })?;
+            assert_eq!(
+                recorder.span_field("origin").as_deref(),
+                Some("http://test.example")
+            );
+            assert_eq!(recorder.span_field("kind").as_deref(), Some("preflight"));
+            assert_eq!(recorder.span_field("outcome").as_deref(), Some("allowed"));
+            assert!(recorder.events.lock().unwrap().is_empty());
+
+            let denied = http::Request::builder()
+                .method(Method::POST)
+                .header(header::ORIGIN, "http://other.example")
+                .body(())?;
+            let result = tracing::subscriber::with_default(recorder.clone(), || {
+                cfg.process_request(&denied)
+            });
+            assert_variant!(result, Err(InvalidRequest::DisallowedOrigin));
+            assert_eq!(
+                recorder.span_field("origin").as_deref(),
+                Some("http://other.example")
+            );
+            assert_eq!(recorder.span_field("kind").as_deref(), Some("simple"));
+            assert_eq!(recorder.span_field("outcome").as_deref(), Some("denied"));
+            assert_eq!(
+                recorder.span_field("reason").as_deref(),
+                Some("disallowed_origin")
+            );
+
+            let events = recorder.events.lock().unwrap();
+            assert_eq!(events.len(), 1);
+            assert!(events[0].contains(&("reason".to_string(), "disallowed_origin".to_string())));
+
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "tower-http")]
+    mod interop {
+        use super::*;
+        use crate::interop::*;
+        use std::time::Duration;
+        use tower_http::cors::{AllowOrigin, CorsLayer};
+
+        fn preflight(origin: &'static str) -> TestResult<http::Request<()>> {
+            Ok(preflight_origin_request_builder()?
+                .header(header::ORIGIN, origin)
+                .body(())?)
+        }
+
+        #[tokio::test]
+        async fn converts_config_to_tower_http() -> TestResult {
+            use tower::{service_fn, Layer, ServiceExt};
+
+            let conversion = CorsBuilder::new()
+                .allow_origins(AllowedOrigins::from_iter(vec![HeaderValue::from_static(
+                    "https://foo.example",
+                )]))
+                .allow_methods(vec![Method::POST])
+                .allow_credentials(true)
+                .max_age(Duration::from_secs(60))
+                .into_config()
+                .to_tower_http();
+
+            assert_eq!(
+                conversion.differences,
+                vec![Difference::NoRejection, Difference::PreflightHandling]
+            );
+
+            let service = conversion.value.layer(service_fn(|_| async {
+                Ok::<_, std::convert::Infallible>(http::Response::new(String::new()))
+            }));
+
+            // Origins are still compared normalized
+            let response = service
+                .clone()
+                .oneshot(preflight("https://FOO.example:443")?)
+                .await?;
+            let headers = response.headers();
+            assert_eq!(
+                headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+                "https://FOO.example:443"
+            );
+            assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "POST");
+            assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+            assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "60");
+
+            let response = service.oneshot(preflight("https://bar.example")?).await?;
+            assert!(!response
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+            Ok(())
+        }
+
+        #[test]
+        fn reports_wildcard_for_null_origin() {
+            let conversion = CorsBuilder::new()
+                .allow_origins(AllowedOrigins::Any { allow_null: false })
+                .prefer_wildcard(true)
+                .into_config()
+                .to_tower_http();
+
+            assert!(conversion
+                .differences
+                .contains(&Difference::WildcardAllowsNullOrigin));
+        }
+
+        #[test]
+        fn converts_tower_http_to_builder() -> TestResult {
+            let layer = CorsLayer::new()
+                .allow_origin(AllowOrigin::mirror_request())
+                .allow_methods(vec![Method::GET, Method::POST])
+                .allow_headers(vec![header::CONTENT_TYPE])
+                .expose_headers(vec![HeaderName::from_static("x-custom")])
+                .max_age(Duration::from_secs(60));
+
+            let conversion = CorsBuilder::from_tower_http(&layer);
+            assert_eq!(
+                conversion.differences,
+                vec![Difference::NoRejection, Difference::PreflightHandling]
+            );
+
+            let cfg = conversion.value.into_config();
+            assert_variant!(
+                cfg.allowed_origins,
+                AllowedOrigins::Any { allow_null: true }
+            );
+            let mut headers = cfg.process_request(&preflight("null")?)?.into_preflight()?;
+            let methods = headers
+                .remove(header::ACCESS_CONTROL_ALLOW_METHODS)
+                .expect("allow-methods header missing");
+            assert_set!(methods, "GET", "POST");
+            assert_eq!(
+                headers[header::ACCESS_CONTROL_ALLOW_HEADERS],
+                "content-type"
+            );
+            assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "60");
+            assert_eq!(
+                cfg.exposed_headers_header,
+                Some(HeaderValue::from_static("x-custom"))
+            );
+
+            Ok(())
+        }
+
+        #[test]
+        fn reports_unrepresentable_tower_http_settings() {
+            let conversion = CorsBuilder::from_tower_http(&CorsLayer::very_permissive());
+            for difference in &[
+                Difference::UnrepresentableMethods,
+                Difference::UnrepresentableHeaders,
+            ] {
+                assert!(conversion.differences.contains(difference));
+            }
+
+            let conversion = CorsBuilder::from_tower_http(&CorsLayer::new().allow_origin(
+                AllowOrigin::list(vec![HeaderValue::from_static("https://foo.example")]),
+            ));
+            assert!(conversion.differences.contains(&Difference::UnknownOrigins));
+        }
+    }
 }